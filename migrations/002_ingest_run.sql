@@ -0,0 +1,20 @@
+-- One row per DBF ingest, so any loaded row can be traced back to the file it came from.
+--
+-- Rows loaded before runs were recorded have no ingest_run_id.
+CREATE SEQUENCE ingest_run_id_seq START 1;
+
+CREATE TABLE ingest_run (
+    run_id BIGINT PRIMARY KEY DEFAULT nextval('ingest_run_id_seq'),
+    source_path TEXT NOT NULL,
+    source_sha256 TEXT,
+    dbf_last_update DATE,
+    dbf_num_records BIGINT,
+    rows_loaded BIGINT,
+    rows_rejected BIGINT,
+    mapping_profile TEXT,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP,
+    tool_version TEXT
+);
+
+ALTER TABLE parcel ADD COLUMN ingest_run_id BIGINT;
@@ -0,0 +1,217 @@
+-- Typed parcel columns: integers for codes, years and counts, DECIMAL for currency and
+-- acreage, and a sale_date DATE. Measures such as stories and structure_area stay DOUBLE.
+--
+-- The table is rebuilt rather than altered so sale_date sits beside sale_price and
+-- ingest_run_id stays last, the order rows are appended in. The views are recreated
+-- once migrations finish.
+CREATE TABLE parcel_typed (
+    id BIGINT,
+    clean_parcel TEXT,
+    county_code INTEGER,
+    county_name TEXT,
+    parcel_id TEXT,
+    district_code INTEGER,
+    district_name TEXT,
+    map TEXT,
+    parcel_number TEXT,
+    suffix TEXT,
+    legal_description TEXT,
+    legal_description_1 TEXT,
+    legal_description_2 TEXT,
+    full_legal_description TEXT,
+    deeded_acre DECIMAL(18, 6),
+    calculated_acre DECIMAL(18, 6),
+    tax_year INTEGER,
+    tax_district TEXT,
+    tax_class TEXT,
+    deed_book TEXT,
+    deed_page TEXT,
+    property_class TEXT,
+    property_type TEXT,
+    owner_1 TEXT,
+    owner_2 TEXT,
+    full_owner_name TEXT,
+    owner_address TEXT,
+    owner_address_1 TEXT,
+    owner_address_2 TEXT,
+    owner_city TEXT,
+    owner_state TEXT,
+    owner_zip TEXT,
+    care_of TEXT,
+    full_owner_address TEXT,
+    new_owner TEXT,
+    new_owner_address TEXT,
+    new_owner_address_1 TEXT,
+    new_owner_address_2 TEXT,
+    full_new_owner TEXT,
+    new_deed_book TEXT,
+    new_deed_page TEXT,
+    physical_number INTEGER,
+    physical_direction TEXT,
+    physical_street TEXT,
+    physical_suffix TEXT,
+    physical_unit_type TEXT,
+    physical_city TEXT,
+    physical_zip TEXT,
+    physical_unit_id TEXT,
+    full_physical_address TEXT,
+    occupancy_description TEXT,
+    hazard_occupancy TEXT,
+    land_use TEXT,
+    land_use_code TEXT,
+    year_built INTEGER,
+    grade TEXT,
+    style_code TEXT,
+    style_description TEXT,
+    commercial INTEGER,
+    stories DOUBLE,
+    commercial_type_1 TEXT,
+    basement_type TEXT,
+    exterior_wall TEXT,
+    exterior_1 TEXT,
+    construction TEXT,
+    total_rooms INTEGER,
+    use_type TEXT,
+    business_license BIGINT,
+    structure_area DOUBLE,
+    cubic_feet DOUBLE,
+    units INTEGER,
+    commercial_type_2 INTEGER,
+    card INTEGER,
+    cards INTEGER,
+    dwelling_value DECIMAL(14, 2),
+    commercial_type_3 INTEGER,
+    other_building DECIMAL(14, 2),
+    land_appraised DECIMAL(14, 2),
+    building_appraised DECIMAL(14, 2),
+    total_appraised DECIMAL(14, 2),
+    sams_address TEXT,
+    sams_city TEXT,
+    sams_state TEXT,
+    sams_zip TEXT,
+    pre_address_number TEXT,
+    address_number TEXT,
+    address_number_suffix TEXT,
+    full_name TEXT,
+    unit_type TEXT,
+    unit_id TEXT,
+    alternate_unit_type TEXT,
+    alternate_unit_id TEXT,
+    flood_risks TEXT,
+    oby_count INTEGER,
+    sale_price DECIMAL(14, 2),
+    sale_date DATE,
+    developer_id TEXT,
+    building_permits INTEGER,
+    ingest_run_id BIGINT
+);
+
+INSERT INTO parcel_typed
+SELECT
+    CAST(id AS BIGINT),
+    clean_parcel,
+    CAST(county_code AS INTEGER),
+    county_name,
+    parcel_id,
+    CAST(district_code AS INTEGER),
+    district_name,
+    map,
+    parcel_number,
+    suffix,
+    legal_description,
+    legal_description_1,
+    legal_description_2,
+    full_legal_description,
+    CAST(deeded_acre AS DECIMAL(18, 6)),
+    CAST(calculated_acre AS DECIMAL(18, 6)),
+    CAST(tax_year AS INTEGER),
+    tax_district,
+    tax_class,
+    deed_book,
+    deed_page,
+    property_class,
+    property_type,
+    owner_1,
+    owner_2,
+    full_owner_name,
+    owner_address,
+    owner_address_1,
+    owner_address_2,
+    owner_city,
+    owner_state,
+    owner_zip,
+    care_of,
+    full_owner_address,
+    new_owner,
+    new_owner_address,
+    new_owner_address_1,
+    new_owner_address_2,
+    full_new_owner,
+    new_deed_book,
+    new_deed_page,
+    CAST(physical_number AS INTEGER),
+    physical_direction,
+    physical_street,
+    physical_suffix,
+    physical_unit_type,
+    physical_city,
+    physical_zip,
+    physical_unit_id,
+    full_physical_address,
+    occupancy_description,
+    hazard_occupancy,
+    land_use,
+    land_use_code,
+    CAST(year_built AS INTEGER),
+    grade,
+    style_code,
+    style_description,
+    CAST(commercial AS INTEGER),
+    stories,
+    commercial_type_1,
+    basement_type,
+    exterior_wall,
+    exterior_1,
+    construction,
+    CAST(total_rooms AS INTEGER),
+    use_type,
+    CAST(business_license AS BIGINT),
+    structure_area,
+    cubic_feet,
+    CAST(units AS INTEGER),
+    CAST(commercial_type_2 AS INTEGER),
+    CAST(card AS INTEGER),
+    CAST(cards AS INTEGER),
+    CAST(dwelling_value AS DECIMAL(14, 2)),
+    CAST(commercial_type_3 AS INTEGER),
+    CAST(other_building AS DECIMAL(14, 2)),
+    CAST(land_appraised AS DECIMAL(14, 2)),
+    CAST(building_appraised AS DECIMAL(14, 2)),
+    CAST(total_appraised AS DECIMAL(14, 2)),
+    sams_address,
+    sams_city,
+    sams_state,
+    sams_zip,
+    pre_address_number,
+    address_number,
+    address_number_suffix,
+    full_name,
+    unit_type,
+    unit_id,
+    alternate_unit_type,
+    alternate_unit_id,
+    flood_risks,
+    CAST(oby_count AS INTEGER),
+    CAST(sale_price AS DECIMAL(14, 2)),
+    NULL,
+    developer_id,
+    CAST(building_permits AS INTEGER),
+    ingest_run_id
+FROM
+    parcel;
+
+DROP VIEW IF EXISTS parcel_summary;
+DROP VIEW IF EXISTS parcel_card;
+DROP VIEW IF EXISTS parcel_card_row;
+DROP TABLE parcel;
+ALTER TABLE parcel_typed RENAME TO parcel;
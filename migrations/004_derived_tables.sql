@@ -0,0 +1,46 @@
+-- Tables derived from parcel, which earlier builds created on first use, and the parcel
+-- outlines loaded from a shapefile.
+--
+-- The derived tables are replaced, since older builds may have created them with other
+-- column types, and are refilled from parcel once migrations finish. Loaded outlines are
+-- kept.
+CREATE OR REPLACE TABLE parcel_flood_zone (
+    parcel_id TEXT,
+    tax_year INTEGER,
+    zone TEXT,
+    sfha BOOLEAN
+);
+
+CREATE OR REPLACE TABLE deed_reference (
+    parcel_id TEXT,
+    tax_year INTEGER,
+    kind TEXT,
+    owner TEXT,
+    raw_book TEXT,
+    raw_page TEXT,
+    book TEXT,
+    page TEXT,
+    valid BOOLEAN
+);
+
+CREATE OR REPLACE TABLE parcel_legal_description (
+    parcel_id TEXT,
+    tax_year INTEGER,
+    subdivision TEXT,
+    lot TEXT,
+    block TEXT,
+    section TEXT,
+    stated_acre DOUBLE,
+    is_part BOOLEAN,
+    is_residue BOOLEAN
+);
+
+CREATE TABLE IF NOT EXISTS parcel_geometry (
+    source_id TEXT,
+    parcel_id TEXT,
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    wkb BLOB
+);
@@ -0,0 +1,12 @@
+-- The centroid of each parcel outline, so comparable parcels can be ranked by proximity.
+--
+-- Outlines loaded before centroids were stored take the centre of their bounding box
+-- until they are loaded again.
+ALTER TABLE parcel_geometry ADD COLUMN centroid_x DOUBLE;
+
+ALTER TABLE parcel_geometry ADD COLUMN centroid_y DOUBLE;
+
+UPDATE parcel_geometry
+SET
+    centroid_x = (min_x + max_x) / 2,
+    centroid_y = (min_y + max_y) / 2;
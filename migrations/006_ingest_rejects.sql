@@ -0,0 +1,19 @@
+-- How each ingest run ended, and the records it rejected.
+--
+-- A run is closed with status 'loaded' or 'failed', the error that stopped a failed one,
+-- and one ingest_reject row per record that couldn't be read. Runs recorded before this
+-- migration are taken as loaded when they finished and failed when they didn't.
+ALTER TABLE ingest_run ADD COLUMN status TEXT;
+
+ALTER TABLE ingest_run ADD COLUMN error TEXT;
+
+UPDATE ingest_run
+SET
+    status = CASE WHEN finished_at IS NULL THEN 'failed' ELSE 'loaded' END;
+
+CREATE TABLE ingest_reject (
+    run_id BIGINT NOT NULL,
+    record BIGINT,
+    column_name TEXT,
+    message TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- The acreage stated in a legal description as a DECIMAL(18, 6) like deeded_acre, so
+-- the two compare exactly.
+--
+-- Stated acreages too large for the column were parsed from text such as a misread
+-- document number; they become NULL, as the parser now leaves them.
+ALTER TABLE parcel_legal_description
+ALTER COLUMN stated_acre TYPE DECIMAL(18, 6) USING TRY_CAST(stated_acre AS DECIMAL(18, 6));
@@ -0,0 +1,96 @@
+-- title = "Appraisals that stand out from comparable parcels"
+-- description = """
+-- Parcels whose appraised value per acre or per square foot of structure is far from the
+-- median of their peers. Peers share land use, style, grade and year-built decade. Scores
+-- are robust z-scores, 0.6745 * (x - median) / MAD, so a handful of extreme parcels cannot
+-- drag the peer baseline with them."""
+--
+-- [[param]]
+-- name = "threshold"
+-- type = "float"
+-- default = 3.5
+-- description = "Smallest absolute score reported"
+--
+-- [[param]]
+-- name = "min_peers"
+-- type = "integer"
+-- default = 5
+-- description = "Skip peer groups smaller than this; their medians are not meaningful"
+--
+-- [[param]]
+-- name = "limit"
+-- type = "integer"
+-- default = 25
+-- description = "Maximum number of parcels to show"
+--
+-- [columns]
+-- "Acre Score" = "number"
+-- "Sq Ft Score" = "number"
+WITH metrics AS (
+    SELECT
+        parcel_id,
+        full_owner_name,
+        land_use,
+        style_description,
+        grade,
+        FLOOR(year_built / 10) * 10 AS decade,
+        total_appraised,
+        total_appraised / NULLIF(deeded_acre, 0) AS value_per_acre,
+        total_appraised / NULLIF(structure_area, 0) AS value_per_sqft
+    FROM
+        parcel_summary
+    WHERE
+        total_appraised > 0
+),
+centred AS (
+    SELECT
+        *,
+        COUNT(*) OVER peers AS peer_count,
+        MEDIAN(value_per_acre) OVER peers AS median_per_acre,
+        MEDIAN(value_per_sqft) OVER peers AS median_per_sqft
+    FROM
+        metrics
+    WINDOW
+        peers AS (PARTITION BY land_use, style_description, grade, decade)
+),
+spread AS (
+    SELECT
+        *,
+        MEDIAN(ABS(value_per_acre - median_per_acre)) OVER peers AS mad_per_acre,
+        MEDIAN(ABS(value_per_sqft - median_per_sqft)) OVER peers AS mad_per_sqft
+    FROM
+        centred
+    WINDOW
+        peers AS (PARTITION BY land_use, style_description, grade, decade)
+),
+scored AS (
+    SELECT
+        *,
+        0.6745 * (value_per_acre - median_per_acre) / NULLIF(mad_per_acre, 0) AS acre_score,
+        0.6745 * (value_per_sqft - median_per_sqft) / NULLIF(mad_per_sqft, 0) AS sqft_score
+    FROM
+        spread
+    WHERE
+        peer_count >= $min_peers
+)
+SELECT
+    parcel_id AS "Parcel ID",
+    full_owner_name AS "Owner",
+    land_use AS "Land Use",
+    peer_count AS "Peers",
+    total_appraised AS "Total Appraised",
+    value_per_acre AS "Per Acre",
+    median_per_acre AS "Peer Median/Acre",
+    acre_score AS "Acre Score",
+    value_per_sqft AS "Per Sq Ft",
+    median_per_sqft AS "Peer Median/Sq Ft",
+    sqft_score AS "Sq Ft Score"
+FROM
+    scored
+WHERE
+    ABS(acre_score) >= $threshold
+    OR ABS(sqft_score) >= $threshold
+ORDER BY
+    GREATEST(ABS(COALESCE(acre_score, 0)), ABS(COALESCE(sqft_score, 0))) DESC
+LIMIT
+    $limit
@@ -0,0 +1,18 @@
+-- title = "Owners with the most parcels"
+--
+-- [[param]]
+-- name = "limit"
+-- type = "integer"
+-- default = 7
+-- description = "Maximum number of owners to show"
+SELECT
+    full_owner_name AS "Owner",
+    COUNT(*) AS "Parcel Count"
+FROM
+    parcel_summary
+GROUP BY
+    full_owner_name
+ORDER BY
+    "Parcel Count" DESC
+LIMIT
+    $limit
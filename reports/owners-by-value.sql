@@ -0,0 +1,18 @@
+-- title = "Owners by total appraised value"
+--
+-- [[param]]
+-- name = "limit"
+-- type = "integer"
+-- default = 7
+-- description = "Maximum number of owners to show"
+SELECT
+    full_owner_name AS "Owner",
+    COALESCE(SUM(total_appraised), 0) AS "Total Appraised Value"
+FROM
+    parcel_summary
+GROUP BY
+    full_owner_name
+ORDER BY
+    "Total Appraised Value" DESC
+LIMIT
+    $limit
@@ -0,0 +1,37 @@
+-- title = "Potential redemption properties"
+-- description = "Low-value parcels with no owner name or address on record"
+--
+-- [[param]]
+-- name = "max_value"
+-- type = "float"
+-- default = 50000
+-- description = "Only include parcels appraised below this"
+--
+-- [[param]]
+-- name = "limit"
+-- type = "integer"
+-- default = 100
+-- description = "Maximum number of parcels to show"
+--
+-- [columns]
+-- "ID" = "text"
+SELECT
+    CAST(id AS BIGINT) AS "ID",
+    full_owner_name AS "Owner",
+    parcel_id AS "Parcel ID",
+    total_appraised AS "Total Appraised",
+    owner_address AS "Owner Address",
+    owner_city AS "Owner City",
+    owner_state AS "Owner State",
+    physical_city AS "Physical City",
+    land_use AS "Land Use"
+FROM
+    parcel_summary
+WHERE
+    total_appraised < $max_value
+    AND (owner_address IS NULL OR owner_address = '')
+    AND (full_owner_name IS NULL OR full_owner_name = '')
+ORDER BY
+    total_appraised ASC
+LIMIT
+    $limit
@@ -0,0 +1,10 @@
+-- title = "Summary"
+-- description = "Headline parcel, owner, acreage and value figures"
+SELECT
+    COUNT(*) AS "Parcels",
+    COUNT(DISTINCT full_owner_name) AS "Owners",
+    SUM(deeded_acre) AS "Acres",
+    SUM(total_appraised) AS "Total Appraised",
+    MEDIAN(total_appraised) AS "Median Appraised"
+FROM
+    parcel_summary
@@ -0,0 +1,24 @@
+-- title = "Valuation by land use"
+-- description = "Appraised value statistics per land use"
+--
+-- [[param]]
+-- name = "limit"
+-- type = "integer"
+-- default = 25
+-- description = "Maximum number of land uses to show"
+SELECT
+    land_use AS "Land Use",
+    COUNT(*) AS "Parcels",
+    MIN(total_appraised) AS "Min Appraised",
+    MEDIAN(total_appraised) AS "Median Appraised",
+    AVG(total_appraised) AS "Mean Appraised",
+    MAX(total_appraised) AS "Max Appraised",
+    SUM(total_appraised) AS "Total Appraised"
+FROM
+    parcel_summary
+GROUP BY
+    land_use
+ORDER BY
+    "Total Appraised" DESC NULLS LAST
+LIMIT
+    $limit
@@ -0,0 +1,146 @@
+use clap::ValueEnum;
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Row};
+use rust_decimal::Decimal;
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// What the build-out report groups lots by.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum BuildoutGrouping {
+    /// Subdivision parsed from the legal description
+    Subdivision,
+    /// Developer ID recorded on the parcel
+    Developer,
+}
+
+impl BuildoutGrouping {
+    /// The SQL expression rows are grouped by, and its header.
+    fn expression(&self) -> (&'static str, &'static str) {
+        match self {
+            BuildoutGrouping::Subdivision => ("l.subdivision", "Subdivision"),
+            BuildoutGrouping::Developer => ("NULLIF(TRIM(p.developer_id), '')", "Developer"),
+        }
+    }
+}
+
+/// Build-out progress for one subdivision or developer.
+struct BuildoutRow {
+    grouping: String,
+    lot_count: i64,
+    built_count: i64,
+    vacant_count: i64,
+    permit_count: i64,
+    median_value: Option<Decimal>,
+    newest_build: Option<i32>,
+}
+
+impl FromRow for BuildoutRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(BuildoutRow {
+            grouping: row.get("grouping")?,
+            lot_count: row.get("lot_count")?,
+            built_count: row.get("built_count")?,
+            vacant_count: row.get("vacant_count")?,
+            permit_count: row.get("permit_count")?,
+            median_value: get_decimal(row, "median_value")?,
+            newest_build: row.get("newest_build")?,
+        })
+    }
+}
+
+/// Returns build-out progress per subdivision or developer.
+///
+/// Each parcel counts as one lot. A lot is built when it has a year built, a building
+/// value or a structure area; otherwise it is vacant. `name` optionally restricts the
+/// report to groups matching a case-insensitive `LIKE` pattern such as `%LGI%`.
+pub fn buildout(
+    db: &Database,
+    grouping: BuildoutGrouping,
+    name: Option<&str>,
+    limit: usize,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query; the grouping expression comes from a fixed list, never from input
+    let (group_expression, group_title) = grouping.expression();
+    let query = format!(
+        "WITH lots AS (
+            SELECT
+                {} AS grouping,
+                p.parcel_id,
+                BOOL_OR(
+                    COALESCE(p.year_built, 0) > 0
+                    OR COALESCE(p.building_appraised, 0) > 0
+                    OR COALESCE(p.structure_area, 0) > 0
+                ) AS built,
+                SUM(COALESCE(p.building_permits, 0)) AS permits,
+                SUM(p.total_appraised) AS total_appraised,
+                MAX(NULLIF(p.year_built, 0)) AS year_built
+            FROM
+                parcel_summary p
+                LEFT JOIN parcel_legal_description l
+                    ON l.parcel_id = p.parcel_id
+                    AND l.tax_year IS NOT DISTINCT FROM p.tax_year
+            GROUP BY
+                ALL
+        )
+        SELECT
+            grouping,
+            COUNT(*) AS lot_count,
+            COUNT(*) FILTER (WHERE built) AS built_count,
+            COUNT(*) FILTER (WHERE NOT built) AS vacant_count,
+            SUM(permits) AS permit_count,
+            MEDIAN(total_appraised) AS median_value,
+            MAX(year_built) AS newest_build
+        FROM
+            lots
+        WHERE
+            grouping IS NOT NULL
+            AND (?::TEXT IS NULL OR grouping ILIKE ?)
+        GROUP BY
+            grouping
+        ORDER BY
+            lot_count DESC
+        LIMIT
+            ?",
+        group_expression,
+    );
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(&query);
+    let name = nullable(name.map(str::to_string));
+    values.extend([name.clone(), name, Value::BigInt(limit as i64)]);
+    let records: Vec<BuildoutRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec![
+        group_title,
+        "Lots",
+        "Built",
+        "Vacant",
+        "Built Out %",
+        "Permits",
+        "Median Value",
+        "Newest Build",
+    ];
+
+    // Callback to generate row data
+    let get_row_data = |record: &BuildoutRow| -> Vec<Value> {
+        vec![
+            record.grouping.clone().into(),
+            record.lot_count.into(),
+            record.built_count.into(),
+            record.vacant_count.into(),
+            (record.built_count as f64 / record.lot_count as f64 * 100.0).into(),
+            record.permit_count.into(),
+            nullable_decimal(record.median_value),
+            nullable(record.newest_build),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
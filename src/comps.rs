@@ -0,0 +1,232 @@
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Row};
+use rust_decimal::Decimal;
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// Relative importance of each attribute when measuring how alike two parcels are.
+///
+/// Numeric attributes are compared in units of their county-wide standard deviation,
+/// categorical ones count as a full unit of distance when they differ. Proximity compares
+/// the centroids of the outlines loaded with the `geometry` subcommand, in units of their
+/// spread across the county, and only counts when both parcels have an outline.
+#[derive(Debug, Clone)]
+pub struct CompWeights {
+    pub structure_area: f64,
+    pub year_built: f64,
+    pub stories: f64,
+    pub total_rooms: f64,
+    pub deeded_acre: f64,
+    pub grade: f64,
+    pub style_code: f64,
+    pub land_use: f64,
+    pub proximity: f64,
+}
+
+impl Default for CompWeights {
+    fn default() -> Self {
+        CompWeights {
+            structure_area: 3.0,
+            year_built: 2.0,
+            stories: 1.0,
+            total_rooms: 1.0,
+            deeded_acre: 1.5,
+            grade: 2.0,
+            style_code: 1.0,
+            land_use: 4.0,
+            proximity: 2.0,
+        }
+    }
+}
+
+/// A parcel found to be similar to the subject parcel.
+struct Comparable {
+    parcel_id: Option<String>,
+    full_owner_name: Option<String>,
+    land_use: Option<String>,
+    structure_area: Option<f64>,
+    year_built: Option<i32>,
+    grade: Option<String>,
+    deeded_acre: Option<Decimal>,
+    sale_price: Option<Decimal>,
+    total_appraised: Option<Decimal>,
+    distance: f64,
+}
+
+impl FromRow for Comparable {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(Comparable {
+            parcel_id: row.get("parcel_id")?,
+            full_owner_name: row.get("full_owner_name")?,
+            land_use: row.get("land_use")?,
+            structure_area: row.get("structure_area")?,
+            year_built: row.get("year_built")?,
+            grade: row.get("grade")?,
+            deeded_acre: get_decimal(row, "deeded_acre")?,
+            sale_price: get_decimal(row, "sale_price")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+            distance: row.get("distance")?,
+        })
+    }
+}
+
+/// Returns the `count` parcels most similar to `parcel_id`, closest first.
+///
+/// Similarity is a weighted Euclidean distance over the attributes in [`CompWeights`].
+/// A missing value on either side counts as one unit of distance for that attribute,
+/// except for proximity, which is left out.
+pub fn find_comparable_parcels(
+    db: &Database,
+    parcel_id: &str,
+    count: usize,
+    weights: &CompWeights,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
+
+    // Make sure the subject parcel exists before ranking everything against it
+    let subject_count: i64 = db.query_row(
+        "SELECT COUNT(*) FROM parcel_summary WHERE parcel_id = ?",
+        [parcel_id],
+        |row| row.get(0),
+    )?;
+    if subject_count == 0 {
+        return Err(Error::NotFound(format!("No parcel found with parcel ID '{}'", parcel_id)));
+    }
+
+    // Prepare the SQL query
+    let query = "
+        WITH centroids AS (
+            SELECT
+                parcel_id,
+                AVG(centroid_x) AS x,
+                AVG(centroid_y) AS y
+            FROM
+                parcel_geometry
+            WHERE
+                parcel_id IS NOT NULL
+            GROUP BY
+                parcel_id
+        ),
+        subject AS (
+            SELECT
+                s.*,
+                c.x AS centroid_x,
+                c.y AS centroid_y
+            FROM
+                main.parcel_summary s
+                LEFT JOIN centroids c
+                    ON c.parcel_id = s.parcel_id
+            WHERE
+                s.parcel_id = ?
+            ORDER BY
+                s.tax_year DESC
+            LIMIT
+                1
+        ),
+        scale AS (
+            SELECT
+                STDDEV_POP(structure_area) AS structure_area,
+                STDDEV_POP(year_built) AS year_built,
+                STDDEV_POP(stories) AS stories,
+                STDDEV_POP(total_rooms) AS total_rooms,
+                STDDEV_POP(deeded_acre) AS deeded_acre,
+                (SELECT VAR_POP(x) + VAR_POP(y) FROM centroids) AS centroid_variance
+            FROM
+                parcel_summary
+        ),
+        distances AS (
+            SELECT
+                p.parcel_id,
+                p.full_owner_name,
+                p.land_use,
+                p.structure_area,
+                p.year_built,
+                p.grade,
+                p.deeded_acre,
+                p.sale_price,
+                p.total_appraised,
+                SQRT(
+                    ? * COALESCE(POW((p.structure_area - s.structure_area) / NULLIF(sc.structure_area, 0), 2), 1)
+                    + ? * COALESCE(POW((p.year_built - s.year_built) / NULLIF(sc.year_built, 0), 2), 1)
+                    + ? * COALESCE(POW((p.stories - s.stories) / NULLIF(sc.stories, 0), 2), 1)
+                    + ? * COALESCE(POW((p.total_rooms - s.total_rooms) / NULLIF(sc.total_rooms, 0), 2), 1)
+                    + ? * COALESCE(POW((p.deeded_acre - s.deeded_acre) / NULLIF(sc.deeded_acre, 0), 2), 1)
+                    + ? * CASE WHEN p.grade IS NOT DISTINCT FROM s.grade THEN 0 ELSE 1 END
+                    + ? * CASE WHEN p.style_code IS NOT DISTINCT FROM s.style_code THEN 0 ELSE 1 END
+                    + ? * CASE WHEN p.land_use IS NOT DISTINCT FROM s.land_use THEN 0 ELSE 1 END
+                    + ? * COALESCE((POW(c.x - s.centroid_x, 2) + POW(c.y - s.centroid_y, 2)) / NULLIF(sc.centroid_variance, 0), 0)
+                ) AS distance
+            FROM
+                parcel_summary p
+                LEFT JOIN centroids c
+                    ON c.parcel_id = p.parcel_id,
+                subject s,
+                scale sc
+            WHERE
+                p.parcel_id IS DISTINCT FROM s.parcel_id
+        )
+        SELECT
+            *
+        FROM
+            distances
+        ORDER BY
+            distance ASC
+        LIMIT
+            ?
+    ";
+
+    // Restrict the candidates to the filtered parcels, then prepare and execute the query
+    let (query, mut values) = filter.scope(query);
+    values.push(Value::Text(parcel_id.to_string()));
+    values.extend(
+        [
+            weights.structure_area,
+            weights.year_built,
+            weights.stories,
+            weights.total_rooms,
+            weights.deeded_acre,
+            weights.grade,
+            weights.style_code,
+            weights.land_use,
+            weights.proximity,
+        ]
+        .map(Value::Double),
+    );
+    values.push(Value::BigInt(count as i64));
+    let records: Vec<Comparable> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec![
+        "Parcel ID",
+        "Owner",
+        "Land Use",
+        "Structure Area",
+        "Year Built",
+        "Grade",
+        "Deeded Acre",
+        "Sale Price",
+        "Total Appraised",
+        "Distance",
+    ];
+
+    // Callback to generate row data
+    let get_row_data = |record: &Comparable| -> Vec<Value> {
+        vec![
+            nullable(record.parcel_id.clone()),
+            nullable(record.full_owner_name.clone()),
+            nullable(record.land_use.clone()),
+            nullable(record.structure_area.map(|v| v as i64)),
+            nullable(record.year_built),
+            nullable(record.grade.clone()),
+            nullable_decimal(record.deeded_acre),
+            nullable_decimal(record.sale_price),
+            nullable_decimal(record.total_appraised),
+            record.distance.into(),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
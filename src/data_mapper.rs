@@ -1,231 +1,414 @@
-use std::error::Error;
-use dbase::{FieldValue, Record};
+use std::str::FromStr;
+use chrono::NaiveDate;
+use dbase::{Date, FieldValue, Record};
+use duckdb::types::Value;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
 use crate::parcel_record::ParcelRecord;
-use duckdb::{Result, ToSql};
+
+/// Names the DBF-to-`ParcelRecord` mapping recorded with each ingest run. Change it whenever
+/// the mapping changes, so rows loaded under different mappings can be told apart.
+pub const MAPPING_PROFILE: &str = "wv-parcel-summary/3";
+
+// DECIMAL (precision, scale) of the currency and acreage columns in the `parcel` table
+const CURRENCY: (u32, u32) = (14, 2);
+const ACREAGE: (u32, u32) = (18, 6);
+
+// Formats dates held in Character fields are read in
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y%m%d", "%m/%d/%Y"];
+
+/// A DBF field whose value doesn't fit the `ParcelRecord` field it maps to.
+#[derive(Debug)]
+pub struct FieldError {
+    pub column: String,
+    pub message: String,
+}
 
 /// Maps a database record to a `ParcelRecord` struct.
-/// Returns a `ParcelRecord` on success or an error on failure.
-pub fn map_record_to_parcel(record: &Record) -> Result<ParcelRecord, Box<dyn Error>> {
-    // Helper function to extract a String field
-    fn get_string_field(record: &Record, field_name: &str) -> Option<String> {
-        match record.get(field_name) {
-            Some(FieldValue::Character(Some(value))) => Some(value.clone()),
-            Some(FieldValue::Character(None)) => None,
-            _ => None,
+/// Returns a `ParcelRecord` on success, or the first field holding a value of the wrong type.
+pub fn map_record_to_parcel(record: &Record) -> Result<ParcelRecord, FieldError> {
+    // A missing field maps to None; a field of another type is an error
+    fn mismatch(field_name: &str, expected: &str, value: &FieldValue) -> FieldError {
+        FieldError {
+            column: field_name.to_string(),
+            message: format!("expected {}, found a {:?} field", expected, value.field_type()),
         }
     }
-    fn get_numeric_field(record: &Record, field_name: &str) -> Option<f64> {
+    fn invalid(field_name: &str, message: String) -> FieldError {
+        FieldError { column: field_name.to_string(), message }
+    }
+    // FoxPro stores Currency as a 64-bit count of ten-thousandths, but dbase hands its
+    // eight bytes back as a double, so the bits are read again as that integer
+    fn currency(value: f64) -> Decimal {
+        Decimal::new(value.to_bits() as i64, 4)
+    }
+    // Helper function to extract a String field from a Character or Memo field
+    fn get_string_field(record: &Record, field_name: &str) -> Result<Option<String>, FieldError> {
         match record.get(field_name) {
+            Some(FieldValue::Character(value)) => Ok(value.clone()),
+            // An empty memo is read as an empty string rather than None like a blank Character
+            Some(FieldValue::Memo(value)) => {
+                let value = value.trim_end_matches([' ', '\0']);
+                Ok(Some(value.to_string()).filter(|v| !v.is_empty()))
+            }
+            Some(other) => Err(mismatch(field_name, "text", other)),
+            None => Ok(None),
+        }
+    }
+    // Helper function to extract a number field, which must be finite
+    fn get_numeric_field(record: &Record, field_name: &str) -> Result<Option<f64>, FieldError> {
+        let value = match record.get(field_name) {
             Some(FieldValue::Numeric(value)) => *value,
-            Some(FieldValue::Float(value)) => value.map(|v| v as f64),
-            Some(FieldValue::Integer(value)) => Some(*value as f64),
-            _ => None,
+            Some(FieldValue::Float(value)) => value.map(f64::from),
+            Some(FieldValue::Integer(value)) => Some(f64::from(*value)),
+            Some(FieldValue::Double(value)) => Some(*value),
+            Some(FieldValue::Currency(value)) => currency(*value).to_f64(),
+            Some(other) => return Err(mismatch(field_name, "a number", other)),
+            None => None,
+        };
+        match value {
+            Some(number) if !number.is_finite() => Err(invalid(field_name, format!("{} is not a number", number))),
+            _ => Ok(value),
+        }
+    }
+    // Helper function to extract a whole number that fits the integer type `T`; a Logical
+    // field counts as 1 or 0
+    fn get_integer_field<T: TryFrom<i64>>(record: &Record, field_name: &str) -> Result<Option<T>, FieldError> {
+        let number = match record.get(field_name) {
+            Some(FieldValue::Logical(value)) => value.map(|v| f64::from(u8::from(v))),
+            _ => get_numeric_field(record, field_name)?,
+        };
+        let Some(number) = number else {
+            return Ok(None);
+        };
+        if number.fract() != 0.0 {
+            return Err(invalid(field_name, format!("expected a whole number, found {}", number)));
+        }
+        let out_of_range = || invalid(field_name, format!("{} is out of range", number));
+        if !(i64::MIN as f64..i64::MAX as f64).contains(&number) {
+            return Err(out_of_range());
+        }
+        T::try_from(number as i64).map(Some).map_err(|_| out_of_range())
+    }
+    // Helper function to extract a number for a DECIMAL(precision, scale) column, rounded
+    // to its scale the way DuckDB rounds a cast
+    fn get_decimal_field(
+        record: &Record,
+        field_name: &str,
+        (precision, scale): (u32, u32),
+    ) -> Result<Option<Decimal>, FieldError> {
+        let value = match record.get(field_name) {
+            Some(FieldValue::Currency(value)) => currency(*value),
+            _ => {
+                let Some(number) = get_numeric_field(record, field_name)? else {
+                    return Ok(None);
+                };
+                // Display gives the shortest digits that read back as the same double, so 0.1
+                // stays 0.1 rather than its binary expansion
+                Decimal::from_str(&number.to_string())
+                    .map_err(|_| invalid(field_name, format!("{} is out of range", number)))?
+            }
+        };
+        let value = value.round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero);
+        if value.abs() >= Decimal::from(10i64.pow(precision - scale)) {
+            return Err(invalid(field_name, format!("{} is out of range", value)));
+        }
+        Ok(Some(value))
+    }
+    // Helper function to extract a date from a Date or DateTime field, dropping the time, or
+    // from text in one of DATE_FORMATS
+    fn get_date_field(record: &Record, field_name: &str) -> Result<Option<NaiveDate>, FieldError> {
+        let to_naive_date = |date: &Date| {
+            NaiveDate::from_ymd_opt(date.year() as i32, date.month(), date.day()).ok_or_else(|| {
+                invalid(
+                    field_name,
+                    format!("{:04}-{:02}-{:02} is not a valid date", date.year(), date.month(), date.day()),
+                )
+            })
+        };
+        match record.get(field_name) {
+            Some(FieldValue::Date(Some(date))) => to_naive_date(date).map(Some),
+            Some(FieldValue::Date(None)) => Ok(None),
+            // A blank DateTime is Julian day 0, which falls in 4713 BC
+            Some(FieldValue::DateTime(datetime)) if datetime.date().year() as i32 <= 0 => Ok(None),
+            Some(FieldValue::DateTime(datetime)) => to_naive_date(&datetime.date()).map(Some),
+            Some(FieldValue::Character(Some(text))) => DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(text.trim(), format).ok())
+                .map(Some)
+                .ok_or_else(|| invalid(field_name, format!("expected a date, found '{}'", text))),
+            Some(FieldValue::Character(None)) => Ok(None),
+            Some(other) => Err(mismatch(field_name, "a date", other)),
+            None => Ok(None),
         }
     }
     let parcel_record = ParcelRecord {
-        id: get_numeric_field(record, "ID"),
+        id: get_integer_field(record, "ID")?,
 
-        clean_parcel: get_string_field(record, "CleanParcel"),
-        county_code: get_numeric_field(record, "CountyCode"),
-        county_name: get_string_field(record, "CountyName"),
-        parcel_id: get_string_field(record, "ParcelID"),
-        district_code: get_numeric_field(record, "DistrictCode"),
-        district_name: get_string_field(record, "DistrictName"),
-        map: get_string_field(record, "Map"),
-        parcel_number: get_string_field(record, "ParcelNumber"),
-        suffix: get_string_field(record, "Suffix"),
-        legal_description: get_string_field(record, "LegalDescription"),
-        legal_description_1: get_string_field(record, "LegalDescription1"),
-        legal_description_2: get_string_field(record, "LegalDescription2"),
-        full_legal_description: get_string_field(record, "FullLegalDescription"),
-        deeded_acre: get_numeric_field(record, "DeededAcre"),
-        calculated_acre: get_numeric_field(record, "CalculatedAcre"),
-        tax_year: get_numeric_field(record, "TaxYear"),
-        tax_district: get_string_field(record, "TaxDistrict"),
-        tax_class: get_string_field(record, "TaxClass"),
-        deed_book: get_string_field(record, "DeedBook"),
-        deed_page: get_string_field(record, "DeedPage"),
-        property_class: get_string_field(record, "PropertyClass"),
-        property_type: get_string_field(record, "PropertyType"),
-        owner_1: get_string_field(record, "Owner1"),
-        owner_2: get_string_field(record, "Owner2"),
-        full_owner_name: get_string_field(record, "FullOwnerName"),
-        owner_address: get_string_field(record, "OwnerAddress"),
-        owner_address_1: get_string_field(record, "OwnerAddress1"),
-        owner_address_2: get_string_field(record, "OwnerAddress2"),
-        owner_city: get_string_field(record, "OwnerCity"),
-        owner_state: get_string_field(record, "OwnerState"),
-        owner_zip: get_string_field(record, "OwnerZip"),
-        care_of: get_string_field(record, "CareOf"),
-        full_owner_address: get_string_field(record, "FullOwnerAddress"),
-        new_owner: get_string_field(record, "NewOwner"),
-        new_owner_address: get_string_field(record, "NewOwnerAddress"),
-        new_owner_address_1: get_string_field(record, "NewOwnerAddress1"),
-        new_owner_address_2: get_string_field(record, "NewOwnerAddress2"),
-        full_new_owner: get_string_field(record, "FullNewOwner"),
-        new_deed_book: get_string_field(record, "NewDeedBook"),
-        new_deed_page: get_string_field(record, "NewDeedPage"),
-        physical_number: get_numeric_field(record, "PhysicalNumber"),
-        physical_direction: get_string_field(record, "PhysicalDirection"),
-        physical_street: get_string_field(record, "PhysicalStreet"),
-        physical_suffix: get_string_field(record, "PhysicalSuffix"),
-        physical_unit_type: get_string_field(record, "PhysicalUnitType"),
-        physical_city: get_string_field(record, "PhysicalCity"),
-        physical_zip: get_string_field(record, "PhysicalZip"),
-        physical_unit_id: get_string_field(record, "PhysicalUnitID"),
-        full_physical_address: get_string_field(record, "FullPhysicalAddress"),
-        occupancy_description: get_string_field(record, "OccupancyDescription"),
-        hazard_occupancy: get_string_field(record, "HazardOccupancy"),
-        land_use: get_string_field(record, "LandUse"),
-        land_use_code: get_string_field(record, "LandUseCode"),
-        year_built: get_numeric_field(record, "YearBuilt"),
-        grade: get_string_field(record, "Grade"),
-        style_code: get_string_field(record, "StyleCode"),
-        style_description: get_string_field(record, "StyleDescription"),
-        commercial: get_numeric_field(record, "Commercial"),
-        stories: get_numeric_field(record, "Stories"),
-        commercial_type_1: get_string_field(record, "CommercialType1"),
-        basement_type: get_string_field(record, "BasementType"),
-        exterior_wall: get_string_field(record, "ExteriorWall"),
-        exterior_1: get_string_field(record, "Exterior1"),
-        construction: get_string_field(record, "Construction"),
-        total_rooms: get_numeric_field(record, "TotalRooms"),
-        use_type: get_string_field(record, "UseType"),
-        business_license: get_numeric_field(record, "BusinessLicense"),
-        structure_area: get_numeric_field(record, "StructureArea"),
-        cubic_feet: get_numeric_field(record, "CubicFeet"),
-        units: get_numeric_field(record, "Units"),
-        commercial_type_2: get_numeric_field(record, "CommercialType2"),
-        card: get_numeric_field(record, "Card"),
-        cards: get_numeric_field(record, "Cards"),
-        dwelling_value: get_numeric_field(record, "DwellingValue"),
-        commercial_type_3: get_numeric_field(record, "CommercialType3"),
-        other_building: get_numeric_field(record, "OtherBuilding"),
-        land_appraised: get_numeric_field(record, "LandApprai"),
-        building_appraised: get_numeric_field(record, "BuildingAp"),
-        total_appraised: get_numeric_field(record, "TotalAppra"),
-        sams_address: get_string_field(record, "SamsAddress"),
-        sams_city: get_string_field(record, "SamsCity"),
-        sams_state: get_string_field(record, "SamsState"),
-        sams_zip: get_string_field(record, "SamsZip"),
-        pre_address_number: get_string_field(record, "PreAddressNumber"),
-        address_number: get_string_field(record, "AddressNumber"),
-        address_number_suffix: get_string_field(record, "AddressNumberSuffix"),
-        full_name: get_string_field(record, "FullName"),
-        unit_type: get_string_field(record, "UnitType"),
-        unit_id: get_string_field(record, "UnitID"),
-        alternate_unit_type: get_string_field(record, "AlternateUnitType"),
-        alternate_unit_id: get_string_field(record, "AlternateUnitID"),
-        flood_risks: get_string_field(record, "FloodRisks"),
-        oby_count: get_numeric_field(record, "ObyCount"),
-        sale_price: get_numeric_field(record, "SalePrice"),
-        developer_id: get_string_field(record, "DeveloperID"),
-        building_permits: get_numeric_field(record, "BuildingPermits"),
+        clean_parcel: get_string_field(record, "CleanParcel")?,
+        county_code: get_integer_field(record, "CountyCode")?,
+        county_name: get_string_field(record, "CountyName")?,
+        parcel_id: get_string_field(record, "ParcelID")?,
+        district_code: get_integer_field(record, "DistrictCode")?,
+        district_name: get_string_field(record, "DistrictName")?,
+        map: get_string_field(record, "Map")?,
+        parcel_number: get_string_field(record, "ParcelNumber")?,
+        suffix: get_string_field(record, "Suffix")?,
+        legal_description: get_string_field(record, "LegalDescription")?,
+        legal_description_1: get_string_field(record, "LegalDescription1")?,
+        legal_description_2: get_string_field(record, "LegalDescription2")?,
+        full_legal_description: get_string_field(record, "FullLegalDescription")?,
+        deeded_acre: get_decimal_field(record, "DeededAcre", ACREAGE)?,
+        calculated_acre: get_decimal_field(record, "CalculatedAcre", ACREAGE)?,
+        tax_year: get_integer_field(record, "TaxYear")?,
+        tax_district: get_string_field(record, "TaxDistrict")?,
+        tax_class: get_string_field(record, "TaxClass")?,
+        deed_book: get_string_field(record, "DeedBook")?,
+        deed_page: get_string_field(record, "DeedPage")?,
+        property_class: get_string_field(record, "PropertyClass")?,
+        property_type: get_string_field(record, "PropertyType")?,
+        owner_1: get_string_field(record, "Owner1")?,
+        owner_2: get_string_field(record, "Owner2")?,
+        full_owner_name: get_string_field(record, "FullOwnerName")?,
+        owner_address: get_string_field(record, "OwnerAddress")?,
+        owner_address_1: get_string_field(record, "OwnerAddress1")?,
+        owner_address_2: get_string_field(record, "OwnerAddress2")?,
+        owner_city: get_string_field(record, "OwnerCity")?,
+        owner_state: get_string_field(record, "OwnerState")?,
+        owner_zip: get_string_field(record, "OwnerZip")?,
+        care_of: get_string_field(record, "CareOf")?,
+        full_owner_address: get_string_field(record, "FullOwnerAddress")?,
+        new_owner: get_string_field(record, "NewOwner")?,
+        new_owner_address: get_string_field(record, "NewOwnerAddress")?,
+        new_owner_address_1: get_string_field(record, "NewOwnerAddress1")?,
+        new_owner_address_2: get_string_field(record, "NewOwnerAddress2")?,
+        full_new_owner: get_string_field(record, "FullNewOwner")?,
+        new_deed_book: get_string_field(record, "NewDeedBook")?,
+        new_deed_page: get_string_field(record, "NewDeedPage")?,
+        physical_number: get_integer_field(record, "PhysicalNumber")?,
+        physical_direction: get_string_field(record, "PhysicalDirection")?,
+        physical_street: get_string_field(record, "PhysicalStreet")?,
+        physical_suffix: get_string_field(record, "PhysicalSuffix")?,
+        physical_unit_type: get_string_field(record, "PhysicalUnitType")?,
+        physical_city: get_string_field(record, "PhysicalCity")?,
+        physical_zip: get_string_field(record, "PhysicalZip")?,
+        physical_unit_id: get_string_field(record, "PhysicalUnitID")?,
+        full_physical_address: get_string_field(record, "FullPhysicalAddress")?,
+        occupancy_description: get_string_field(record, "OccupancyDescription")?,
+        hazard_occupancy: get_string_field(record, "HazardOccupancy")?,
+        land_use: get_string_field(record, "LandUse")?,
+        land_use_code: get_string_field(record, "LandUseCode")?,
+        year_built: get_integer_field(record, "YearBuilt")?,
+        grade: get_string_field(record, "Grade")?,
+        style_code: get_string_field(record, "StyleCode")?,
+        style_description: get_string_field(record, "StyleDescription")?,
+        commercial: get_integer_field(record, "Commercial")?,
+        stories: get_numeric_field(record, "Stories")?,
+        commercial_type_1: get_string_field(record, "CommercialType1")?,
+        basement_type: get_string_field(record, "BasementType")?,
+        exterior_wall: get_string_field(record, "ExteriorWall")?,
+        exterior_1: get_string_field(record, "Exterior1")?,
+        construction: get_string_field(record, "Construction")?,
+        total_rooms: get_integer_field(record, "TotalRooms")?,
+        use_type: get_string_field(record, "UseType")?,
+        business_license: get_integer_field(record, "BusinessLicense")?,
+        structure_area: get_numeric_field(record, "StructureArea")?,
+        cubic_feet: get_numeric_field(record, "CubicFeet")?,
+        units: get_integer_field(record, "Units")?,
+        commercial_type_2: get_integer_field(record, "CommercialType2")?,
+        card: get_integer_field(record, "Card")?,
+        cards: get_integer_field(record, "Cards")?,
+        dwelling_value: get_decimal_field(record, "DwellingValue", CURRENCY)?,
+        commercial_type_3: get_integer_field(record, "CommercialType3")?,
+        other_building: get_decimal_field(record, "OtherBuilding", CURRENCY)?,
+        land_appraised: get_decimal_field(record, "LandApprai", CURRENCY)?,
+        building_appraised: get_decimal_field(record, "BuildingAp", CURRENCY)?,
+        total_appraised: get_decimal_field(record, "TotalAppra", CURRENCY)?,
+        sams_address: get_string_field(record, "SamsAddress")?,
+        sams_city: get_string_field(record, "SamsCity")?,
+        sams_state: get_string_field(record, "SamsState")?,
+        sams_zip: get_string_field(record, "SamsZip")?,
+        pre_address_number: get_string_field(record, "PreAddressNumber")?,
+        address_number: get_string_field(record, "AddressNumber")?,
+        address_number_suffix: get_string_field(record, "AddressNumberSuffix")?,
+        full_name: get_string_field(record, "FullName")?,
+        unit_type: get_string_field(record, "UnitType")?,
+        unit_id: get_string_field(record, "UnitID")?,
+        alternate_unit_type: get_string_field(record, "AlternateUnitType")?,
+        alternate_unit_id: get_string_field(record, "AlternateUnitID")?,
+        flood_risks: get_string_field(record, "FloodRisks")?,
+        oby_count: get_integer_field(record, "ObyCount")?,
+        sale_price: get_decimal_field(record, "SalePrice", CURRENCY)?,
+        sale_date: get_date_field(record, "SaleDate")?,
+        developer_id: get_string_field(record, "DeveloperID")?,
+        building_permits: get_integer_field(record, "BuildingPermits")?,
     };
 
     Ok(parcel_record)
 }
 
 
-pub fn parcel_record_2_sql(record: &ParcelRecord) -> Vec<&dyn ToSql> {
-    let values: Vec<&dyn ToSql> = vec![
-        &record.id,
-        &record.clean_parcel,
-        &record.county_code,
-        &record.county_name,
-        &record.parcel_id,
-        &record.district_code,
-        &record.district_name,
-        &record.map,
-        &record.parcel_number,
-        &record.suffix,
-        &record.legal_description,
-        &record.legal_description_1,
-        &record.legal_description_2,
-        &record.full_legal_description,
-        &record.deeded_acre,
-        &record.calculated_acre,
-        &record.tax_year,
-        &record.tax_district,
-        &record.tax_class,
-        &record.deed_book,
-        &record.deed_page,
-        &record.property_class,
-        &record.property_type,
-        &record.owner_1,
-        &record.owner_2,
-        &record.full_owner_name,
-        &record.owner_address,
-        &record.owner_address_1,
-        &record.owner_address_2,
-        &record.owner_city,
-        &record.owner_state,
-        &record.owner_zip,
-        &record.care_of,
-        &record.full_owner_address,
-        &record.new_owner,
-        &record.new_owner_address,
-        &record.new_owner_address_1,
-        &record.new_owner_address_2,
-        &record.full_new_owner,
-        &record.new_deed_book,
-        &record.new_deed_page,
-        &record.physical_number,
-        &record.physical_direction,
-        &record.physical_street,
-        &record.physical_suffix,
-        &record.physical_unit_type,
-        &record.physical_city,
-        &record.physical_zip,
-        &record.physical_unit_id,
-        &record.full_physical_address,
-        &record.occupancy_description,
-        &record.hazard_occupancy,
-        &record.land_use,
-        &record.land_use_code,
-        &record.year_built,
-        &record.grade,
-        &record.style_code,
-        &record.style_description,
-        &record.commercial,
-        &record.stories,
-        &record.commercial_type_1,
-        &record.basement_type,
-        &record.exterior_wall,
-        &record.exterior_1,
-        &record.construction,
-        &record.total_rooms,
-        &record.use_type,
-        &record.business_license,
-        &record.structure_area,
-        &record.cubic_feet,
-        &record.units,
-        &record.commercial_type_2,
-        &record.card,
-        &record.cards,
-        &record.dwelling_value,
-        &record.commercial_type_3,
-        &record.other_building,
-        &record.land_appraised,
-        &record.building_appraised,
-        &record.total_appraised,
-        &record.sams_address,
-        &record.sams_city,
-        &record.sams_state,
-        &record.sams_zip,
-        &record.pre_address_number,
-        &record.address_number,
-        &record.address_number_suffix,
-        &record.full_name,
-        &record.unit_type,
-        &record.unit_id,
-        &record.alternate_unit_type,
-        &record.alternate_unit_id,
-        &record.flood_risks,
-        &record.oby_count,
-        &record.sale_price,
-        &record.developer_id,
-        &record.building_permits,
-    ];
-    values
-}
\ No newline at end of file
+/// The record's values in the `parcel` table's column order, ready to append.
+///
+/// Decimals and dates go as text for DuckDB to cast to the column type, since the
+/// appender can't take a DECIMAL value itself.
+pub fn parcel_record_2_sql(record: &ParcelRecord) -> Vec<Value> {
+    fn decimal(value: Option<Decimal>) -> Value {
+        value.map_or(Value::Null, |v| Value::Text(v.to_string()))
+    }
+    fn date(value: Option<NaiveDate>) -> Value {
+        value.map_or(Value::Null, |v| Value::Text(v.to_string()))
+    }
+    vec![
+        record.id.into(),
+        record.clean_parcel.clone().into(),
+        record.county_code.into(),
+        record.county_name.clone().into(),
+        record.parcel_id.clone().into(),
+        record.district_code.into(),
+        record.district_name.clone().into(),
+        record.map.clone().into(),
+        record.parcel_number.clone().into(),
+        record.suffix.clone().into(),
+        record.legal_description.clone().into(),
+        record.legal_description_1.clone().into(),
+        record.legal_description_2.clone().into(),
+        record.full_legal_description.clone().into(),
+        decimal(record.deeded_acre),
+        decimal(record.calculated_acre),
+        record.tax_year.into(),
+        record.tax_district.clone().into(),
+        record.tax_class.clone().into(),
+        record.deed_book.clone().into(),
+        record.deed_page.clone().into(),
+        record.property_class.clone().into(),
+        record.property_type.clone().into(),
+        record.owner_1.clone().into(),
+        record.owner_2.clone().into(),
+        record.full_owner_name.clone().into(),
+        record.owner_address.clone().into(),
+        record.owner_address_1.clone().into(),
+        record.owner_address_2.clone().into(),
+        record.owner_city.clone().into(),
+        record.owner_state.clone().into(),
+        record.owner_zip.clone().into(),
+        record.care_of.clone().into(),
+        record.full_owner_address.clone().into(),
+        record.new_owner.clone().into(),
+        record.new_owner_address.clone().into(),
+        record.new_owner_address_1.clone().into(),
+        record.new_owner_address_2.clone().into(),
+        record.full_new_owner.clone().into(),
+        record.new_deed_book.clone().into(),
+        record.new_deed_page.clone().into(),
+        record.physical_number.into(),
+        record.physical_direction.clone().into(),
+        record.physical_street.clone().into(),
+        record.physical_suffix.clone().into(),
+        record.physical_unit_type.clone().into(),
+        record.physical_city.clone().into(),
+        record.physical_zip.clone().into(),
+        record.physical_unit_id.clone().into(),
+        record.full_physical_address.clone().into(),
+        record.occupancy_description.clone().into(),
+        record.hazard_occupancy.clone().into(),
+        record.land_use.clone().into(),
+        record.land_use_code.clone().into(),
+        record.year_built.into(),
+        record.grade.clone().into(),
+        record.style_code.clone().into(),
+        record.style_description.clone().into(),
+        record.commercial.into(),
+        record.stories.into(),
+        record.commercial_type_1.clone().into(),
+        record.basement_type.clone().into(),
+        record.exterior_wall.clone().into(),
+        record.exterior_1.clone().into(),
+        record.construction.clone().into(),
+        record.total_rooms.into(),
+        record.use_type.clone().into(),
+        record.business_license.into(),
+        record.structure_area.into(),
+        record.cubic_feet.into(),
+        record.units.into(),
+        record.commercial_type_2.into(),
+        record.card.into(),
+        record.cards.into(),
+        decimal(record.dwelling_value),
+        record.commercial_type_3.into(),
+        decimal(record.other_building),
+        decimal(record.land_appraised),
+        decimal(record.building_appraised),
+        decimal(record.total_appraised),
+        record.sams_address.clone().into(),
+        record.sams_city.clone().into(),
+        record.sams_state.clone().into(),
+        record.sams_zip.clone().into(),
+        record.pre_address_number.clone().into(),
+        record.address_number.clone().into(),
+        record.address_number_suffix.clone().into(),
+        record.full_name.clone().into(),
+        record.unit_type.clone().into(),
+        record.unit_id.clone().into(),
+        record.alternate_unit_type.clone().into(),
+        record.alternate_unit_id.clone().into(),
+        record.flood_risks.clone().into(),
+        record.oby_count.into(),
+        decimal(record.sale_price),
+        date(record.sale_date),
+        record.developer_id.clone().into(),
+        record.building_permits.into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Maps a record holding nothing but `value` in the field `field_name`
+    fn map_field(field_name: &str, value: FieldValue) -> Result<ParcelRecord, FieldError> {
+        let mut record = Record::default();
+        record.insert(field_name.to_string(), value);
+        map_record_to_parcel(&record)
+    }
+
+    #[test]
+    fn rejects_numbers_that_do_not_fit_their_column() {
+        let currency = |ten_thousandths: i64| FieldValue::Currency(f64::from_bits(ten_thousandths as u64));
+        let cases = [
+            // Whole numbers
+            ("TaxYear", FieldValue::Numeric(Some(2024.5)), "expected a whole number, found 2024.5"),
+            ("Card", FieldValue::Double(-0.25), "expected a whole number, found -0.25"),
+            ("TaxYear", FieldValue::Numeric(Some(f64::NAN)), "NaN is not a number"),
+            ("TaxYear", FieldValue::Numeric(Some(3e9)), "3000000000 is out of range"),
+            ("ID", FieldValue::Numeric(Some(1e19)), "10000000000000000000 is out of range"),
+            ("ID", FieldValue::Double(f64::NEG_INFINITY), "-inf is not a number"),
+            // DECIMAL(14, 2) currency
+            ("TotalAppra", FieldValue::Numeric(Some(1e12)), "1000000000000 is out of range"),
+            ("TotalAppra", FieldValue::Numeric(Some(999_999_999_999.996)), "1000000000000.00 is out of range"),
+            ("SalePrice", currency(i64::MIN), "-922337203685477.58 is out of range"),
+            // DECIMAL(18, 6) acreage
+            ("DeededAcre", FieldValue::Numeric(Some(-1e12)), "-1000000000000 is out of range"),
+            ("DeededAcre", FieldValue::Double(1e30), "1000000000000000000000000000000 is out of range"),
+        ];
+        for (field_name, value, message) in cases {
+            let error = map_field(field_name, value).unwrap_err();
+            assert_eq!((error.column.as_str(), error.message.as_str()), (field_name, message));
+        }
+    }
+
+    #[test]
+    fn rounds_numbers_to_their_column_scale() {
+        let cases = [
+            ("TotalAppra", 999_999_999_999.99, Decimal::new(99_999_999_999_999, 2)),
+            ("TotalAppra", 0.125, Decimal::new(13, 2)),
+            ("TotalAppra", -0.125, Decimal::new(-13, 2)),
+            ("DeededAcre", 0.1, Decimal::new(1, 1)),
+            ("DeededAcre", 1.0000005, Decimal::new(1_000_001, 6)),
+        ];
+        for (field_name, number, expected) in cases {
+            let parcel = map_field(field_name, FieldValue::Numeric(Some(number))).unwrap();
+            assert_eq!(parcel.total_appraised.or(parcel.deeded_acre), Some(expected), "{} {}", field_name, number);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::ops::Deref;
+use clap::Args;
+use duckdb::{AccessMode, Config, Connection};
+use crate::error::Error;
+use crate::migrations::migrate;
+
+/// The database file used when no other is given.
+pub const DEFAULT_PATH: &str = "parcel_data.db";
+
+/// How the parcel database is opened.
+#[derive(Debug, Clone, Args)]
+pub struct DatabaseOptions {
+    /// DuckDB database file
+    #[arg(long = "database", global = true, value_name = "FILE", default_value = DEFAULT_PATH)]
+    pub path: String,
+    /// Open the database read-only, so several processes can share it
+    #[arg(long, global = true)]
+    pub read_only: bool,
+    /// Use an empty in-memory database instead of the file; nothing is saved
+    #[arg(long, global = true, conflicts_with = "read_only")]
+    pub in_memory: bool,
+    /// Number of threads DuckDB runs queries on; DuckDB picks when omitted
+    #[arg(long = "db-threads", global = true, value_name = "N")]
+    pub threads: Option<usize>,
+    /// Most memory DuckDB may use, e.g. 4GB; DuckDB picks when omitted
+    #[arg(long, global = true, value_name = "SIZE")]
+    pub memory_limit: Option<String>,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        DatabaseOptions {
+            path: DEFAULT_PATH.to_string(),
+            read_only: false,
+            in_memory: false,
+            threads: None,
+            memory_limit: None,
+        }
+    }
+}
+
+impl DatabaseOptions {
+    /// Options for the database file at `path`, otherwise the defaults.
+    pub fn file(path: impl Into<String>) -> Self {
+        DatabaseOptions { path: path.into(), ..DatabaseOptions::default() }
+    }
+
+    /// Options for an empty in-memory database, as used by tests.
+    pub fn in_memory() -> Self {
+        DatabaseOptions { in_memory: true, ..DatabaseOptions::default() }
+    }
+
+    /// The same options, but read-only unless the database is in memory.
+    pub fn read_only(self) -> Self {
+        DatabaseOptions { read_only: !self.in_memory, ..self }
+    }
+
+    fn config(&self) -> duckdb::Result<Config> {
+        let mut config = Config::default();
+        if self.read_only {
+            config = config.access_mode(AccessMode::ReadOnly)?;
+        }
+        if let Some(threads) = self.threads {
+            config = config.threads(threads as i64)?;
+        }
+        if let Some(memory_limit) = &self.memory_limit {
+            config = config.max_memory(memory_limit)?;
+        }
+        Ok(config)
+    }
+}
+
+/// An open parcel database, shared by everything that reads or writes it.
+///
+/// Dereferences to its DuckDB [`Connection`], so it can be passed wherever one is expected.
+pub struct Database {
+    conn: Connection,
+    name: String,
+    read_only: bool,
+}
+
+impl Database {
+    /// Opens the database `options` describe, creating the file if it doesn't exist and
+    /// the database isn't read-only, and migrates it to the current schema.
+    pub fn open(options: &DatabaseOptions) -> Result<Database, Error> {
+        let name = if options.in_memory { ":memory:".to_string() } else { options.path.clone() };
+        let open_error = |source| Error::OpenDatabase { path: name.clone(), source: Box::new(source) };
+        let config = options.config().map_err(open_error)?;
+        let mut conn = if options.in_memory {
+            Connection::open_in_memory_with_flags(config)
+        } else {
+            Connection::open_with_flags(&options.path, config)
+        }
+        .map_err(open_error)?;
+        migrate(&mut conn, &name, options.read_only)?;
+        Ok(Database { conn, name, read_only: options.read_only })
+    }
+
+    /// Opens the database file at `path` with the default settings.
+    pub fn open_file(path: &str) -> Result<Database, Error> {
+        Database::open(&DatabaseOptions::file(path))
+    }
+
+    /// Opens an empty in-memory database.
+    pub fn in_memory() -> Result<Database, Error> {
+        Database::open(&DatabaseOptions::in_memory())
+    }
+
+    /// Opens another connection to the same database, for use on another thread.
+    pub fn try_clone(&self) -> Result<Database, Error> {
+        let conn = self.conn.try_clone().map_err(|source| Error::OpenDatabase {
+            path: self.name.clone(),
+            source: Box::new(source),
+        })?;
+        Ok(Database { conn, name: self.name.clone(), read_only: self.read_only })
+    }
+
+    /// The database file, or `:memory:` for an in-memory database.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the database was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The underlying DuckDB connection.
+    pub fn conn(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl Deref for Database {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
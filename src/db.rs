@@ -1,170 +1,402 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use duckdb::{Connection, ToSql};
-use crate::data_mapper::parcel_record_2_sql;
+use std::time::{SystemTime, UNIX_EPOCH};
+use duckdb::types::{TimeUnit, Value};
+use duckdb::{appender_params_from_iter, params, Connection};
+use sha2::{Digest, Sha256};
+use crate::data_mapper::{parcel_record_2_sql, MAPPING_PROFILE};
+use crate::database::Database;
+use crate::dbf::DbfFile;
+use crate::error::Error;
 use crate::parcel_record::ParcelRecord;
+use crate::{dbf, deed, flood, legal_description};
 
-pub fn save_to_duckdb(db_path: &str, records: &[ParcelRecord]) -> duckdb::Result<()> {
-    // Create a new DuckDB connection
-    let conn = Arc::new(Mutex::new(create_connection(db_path)?));
+/// The outcome of loading a DBF file.
+pub struct IngestRun {
+    /// The `ingest_run` row recording the load
+    pub run_id: i64,
+    pub rows_loaded: usize,
+    /// The records that couldn't be read and were left out, each as the error naming it
+    pub rejected: Vec<Error>,
+}
 
-    // Ensure the parcel table exists
-    create_parcel_table(&conn)?;
+/// Loads a parcel DBF file into `db` and rebuilds the tables derived from the parcels.
+/// Records that can't be read are skipped and returned as rejected.
+///
+/// Each load is recorded as a row of `ingest_run` holding the file's path, SHA-256 and
+/// header, and every row it loads carries that run's `ingest_run_id`. The rows, the
+/// derived tables and the run's rejected records, kept in `ingest_reject`, are written in
+/// one transaction, so a load that stops part way through leaves none of them behind. The
+/// run is closed with status `loaded`, or `failed` and the error.
+pub fn load_dbf(dbf_path: impl AsRef<Path>, db: &Database) -> Result<IngestRun, Error> {
+    let dbf_path = dbf_path.as_ref();
+    let started_at = now();
+    let sha256 = file_sha256(dbf_path)?;
+    let file = dbf::read_file(dbf_path)?;
+    let run_id = start_ingest_run(db, dbf_path, &file, &sha256, started_at)?;
 
-    // Process records in chunks and save them to the database
-    let chunk_size = 256;
-    let record_chunks: Vec<&[ParcelRecord]> = records.chunks(chunk_size).collect();
-    save_records_in_chunks(&conn, &record_chunks);
+    // The chunk threads share a second connection, which holds the transaction
+    let conn = Arc::new(Mutex::new(db.try_clone()?));
+    conn.lock().expect("Failed to acquire the lock on connection").execute_batch("BEGIN TRANSACTION")?;
+    let loaded = save_records_in_chunks(&conn, &file.records, &file.record_numbers, Some(run_id)).and_then(|()| {
+        let conn = conn.lock().expect("Failed to acquire the lock on connection");
+        rebuild_derived_tables(&conn)?;
+        save_rejects(&conn, run_id, &file.rejected)
+    });
+    let conn = conn.lock().expect("Failed to acquire the lock on connection");
+    let loaded = match loaded {
+        Ok(()) => conn.execute_batch("COMMIT").map_err(Error::from),
+        Err(e) => {
+            conn.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    };
 
-    println!("Saved records to DuckDB");
+    // Close the run either way
+    let (status, error) = match &loaded {
+        Ok(()) => ("loaded", None),
+        Err(e) => ("failed", Some(e.to_string())),
+    };
+    db.execute(
+        "UPDATE ingest_run
+        SET
+            rows_loaded = (SELECT COUNT(*) FROM parcel WHERE ingest_run_id = ?),
+            rows_rejected = ?,
+            finished_at = ?,
+            status = ?,
+            error = ?
+        WHERE
+            run_id = ?",
+        params![run_id, file.rejected.len() as i64, now(), status, error, run_id],
+    )?;
+    loaded?;
 
-    Ok(())
+    Ok(IngestRun {
+        run_id,
+        rows_loaded: file.records.len(),
+        rejected: file.rejected,
+    })
 }
 
-// Function to create a new DuckDB connection
-fn create_connection(db_path: &str) -> duckdb::Result<Connection> {
-    Connection::open(db_path)
+// Function to hash a file's contents with SHA-256, as lower-case hex
+fn file_sha256(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path).map_err(|e| Error::file(path, e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| Error::file(path, e))?;
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-// Function to create the parcel table if it doesn't exist
-fn create_parcel_table(conn: &Arc<Mutex<Connection>>) -> duckdb::Result<()> {
-    let conn = conn.lock().unwrap();
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS parcel (
-            id DOUBLE,
-            clean_parcel TEXT,
-            county_code DOUBLE,
-            county_name TEXT,
-            parcel_id TEXT,
-            district_code DOUBLE,
-            district_name TEXT,
-            map TEXT,
-            parcel_number TEXT,
-            suffix TEXT,
-            legal_description TEXT,
-            legal_description_1 TEXT,
-            legal_description_2 TEXT,
-            full_legal_description TEXT,
-            deeded_acre DOUBLE,
-            calculated_acre DOUBLE,
-            tax_year DOUBLE,
-            tax_district TEXT,
-            tax_class TEXT,
-            deed_book TEXT,
-            deed_page TEXT,
-            property_class TEXT,
-            property_type TEXT,
-            owner_1 TEXT,
-            owner_2 TEXT,
-            full_owner_name TEXT,
-            owner_address TEXT,
-            owner_address_1 TEXT,
-            owner_address_2 TEXT,
-            owner_city TEXT,
-            owner_state TEXT,
-            owner_zip TEXT,
-            care_of TEXT,
-            full_owner_address TEXT,
-            new_owner TEXT,
-            new_owner_address TEXT,
-            new_owner_address_1 TEXT,
-            new_owner_address_2 TEXT,
-            full_new_owner TEXT,
-            new_deed_book TEXT,
-            new_deed_page TEXT,
-            physical_number DOUBLE,
-            physical_direction TEXT,
-            physical_street TEXT,
-            physical_suffix TEXT,
-            physical_unit_type TEXT,
-            physical_city TEXT,
-            physical_zip TEXT,
-            physical_unit_id TEXT,
-            full_physical_address TEXT,
-            occupancy_description TEXT,
-            hazard_occupancy TEXT,
-            land_use TEXT,
-            land_use_code TEXT,
-            year_built DOUBLE,
-            grade TEXT,
-            style_code TEXT,
-            style_description TEXT,
-            commercial DOUBLE,
-            stories DOUBLE,
-            commercial_type_1 TEXT,
-            basement_type TEXT,
-            exterior_wall TEXT,
-            exterior_1 TEXT,
-            construction TEXT,
-            total_rooms DOUBLE,
-            use_type TEXT,
-            business_license DOUBLE,
-            structure_area DOUBLE,
-            cubic_feet DOUBLE,
-            units DOUBLE,
-            commercial_type_2 DOUBLE,
-            card DOUBLE,
-            cards DOUBLE,
-            dwelling_value DOUBLE,
-            commercial_type_3 DOUBLE,
-            other_building DOUBLE,
-            land_appraised DOUBLE,
-            building_appraised DOUBLE,
-            total_appraised DOUBLE,
-            sams_address TEXT,
-            sams_city TEXT,
-            sams_state TEXT,
-            sams_zip TEXT,
-            pre_address_number TEXT,
-            address_number TEXT,
-            address_number_suffix TEXT,
-            full_name TEXT,
-            unit_type TEXT,
-            unit_id TEXT,
-            alternate_unit_type TEXT,
-            alternate_unit_id TEXT,
-            flood_risks TEXT,
-            oby_count DOUBLE,
-            sale_price DOUBLE,
-            developer_id TEXT,
-            building_permits DOUBLE
-        )",
-        [],
-    )?;
-    Ok(())
+// Function to record the start of an ingest run, returning its ID
+fn start_ingest_run(
+    db: &Database,
+    path: &Path,
+    file: &DbfFile,
+    sha256: &str,
+    started_at: Value,
+) -> Result<i64, Error> {
+    // An absolute path still finds the file when the database is used from elsewhere
+    let source_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    db.query_row(
+        "INSERT INTO ingest_run (
+            source_path,
+            source_sha256,
+            dbf_last_update,
+            dbf_num_records,
+            mapping_profile,
+            started_at,
+            tool_version
+        )
+        VALUES
+            (?, ?, CAST(? AS DATE), ?, ?, ?, ?)
+        RETURNING
+            run_id",
+        params![
+            source_path.to_string_lossy(),
+            sha256,
+            format!(
+                "{:04}-{:02}-{:02}",
+                file.last_update.year(),
+                file.last_update.month(),
+                file.last_update.day()
+            ),
+            file.num_records as i64,
+            MAPPING_PROFILE,
+            started_at,
+            env!("CARGO_PKG_VERSION"),
+        ],
+        |row| row.get(0),
+    )
+    .map_err(Error::append("ingest_run"))
+}
+
+// Function to record the records an ingest run rejected, with where they are in the file
+fn save_rejects(conn: &Connection, run_id: i64, rejected: &[Error]) -> Result<(), Error> {
+    let mut appender = conn.appender("ingest_reject").map_err(Error::append("ingest_reject"))?;
+    for error in rejected {
+        let (record, column, message) = match error {
+            Error::CorruptDbf { record, column, source, .. } => (*record, column.clone(), source.to_string()),
+            Error::Mapping { record, column, message, .. } => (Some(*record), Some(column.clone()), message.clone()),
+            other => (None, None, other.to_string()),
+        };
+        appender
+            .append_row(params![run_id, record.map(|record| record as i64), column, message])
+            .map_err(Error::append("ingest_reject"))?;
+    }
+    appender.flush().map_err(Error::append("ingest_reject"))
+}
+
+// The current time as a DuckDB timestamp, in UTC
+fn now() -> Value {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Value::Timestamp(TimeUnit::Microsecond, since_epoch.as_micros() as i64)
 }
 
-// Function to save records in chunks using multiple threads
-fn save_records_in_chunks(conn: &Arc<Mutex<Connection>>, record_chunks: &[&[ParcelRecord]]) {
+// Records appended by each chunk thread
+const CHUNK_SIZE: usize = 256;
+
+/// Appends parcel records to the `parcel` table, tagged with the ingest run that loaded
+/// them when there is one. Errors number the records by their place in `records`.
+pub fn save_to_duckdb(db: &Database, records: &[ParcelRecord], run_id: Option<i64>) -> Result<(), Error> {
+    // Process records in chunks on a second connection shared by the chunk threads
+    let conn = Arc::new(Mutex::new(db.try_clone()?));
+    let numbers: Vec<usize> = (1..=records.len()).collect();
+    save_records_in_chunks(&conn, records, &numbers, run_id)
+}
+
+/// Rebuilds the tables derived from the parcel table: flood zones, deed references and
+/// parsed legal descriptions.
+pub fn rebuild_derived_tables(conn: &Connection) -> Result<(), Error> {
+    flood::build_flood_zone_table(conn)?;
+    deed::build_deed_reference_table(conn)?;
+    legal_description::build_legal_description_table(conn)
+}
+
+// Columns describing a single building card; everything else belongs to the parcel
+const CARD_COLUMNS: &str = "
+    card,
+    cards,
+    year_built,
+    grade,
+    style_code,
+    style_description,
+    commercial,
+    stories,
+    commercial_type_1,
+    basement_type,
+    exterior_wall,
+    exterior_1,
+    construction,
+    total_rooms,
+    use_type,
+    structure_area,
+    cubic_feet,
+    units,
+    commercial_type_2,
+    commercial_type_3,
+    occupancy_description,
+    dwelling_value,
+    other_building,
+    building_appraised";
+
+// Function to create the card-level and parcel-level views over the parcel table.
+//
+// The DBF has one row per building card, so a parcel with several buildings appears
+// several times with its land value repeated on each row. `parcel_card` holds one row
+// per card with the building columns, and `parcel_summary` rolls the cards up into one
+// row per parcel and tax year: building values and areas are summed across cards, land
+// is counted once, and the descriptive building columns come from the lowest card.
+// Re-ingesting the same file appends duplicate cards, so both views keep one row per
+// parcel, tax year and card.
+pub fn create_parcel_views(conn: &Connection) -> duckdb::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE OR REPLACE VIEW parcel_card_row AS
+        SELECT
+            *,
+            COALESCE(parcel_id, clean_parcel, CAST(id AS TEXT)) AS parcel_key
+        FROM
+            parcel
+        QUALIFY
+            ROW_NUMBER() OVER (PARTITION BY parcel_key, tax_year, card ORDER BY id) = 1;
+
+        CREATE OR REPLACE VIEW parcel_card AS
+        SELECT
+            parcel_id,
+            tax_year,
+            {}
+        FROM
+            parcel_card_row;
+
+        CREATE OR REPLACE VIEW parcel_summary AS
+        WITH cards AS (
+            SELECT
+                *,
+                ROW_NUMBER() OVER parcel_cards AS card_rank,
+                COUNT(*) OVER parcel_cards AS card_count,
+                SUM(building_appraised) OVER parcel_cards AS parcel_building_appraised,
+                SUM(dwelling_value) OVER parcel_cards AS parcel_dwelling_value,
+                SUM(other_building) OVER parcel_cards AS parcel_other_building,
+                SUM(structure_area) OVER parcel_cards AS parcel_structure_area,
+                SUM(cubic_feet) OVER parcel_cards AS parcel_cubic_feet,
+                SUM(units) OVER parcel_cards AS parcel_units,
+                SUM(total_rooms) OVER parcel_cards AS parcel_total_rooms
+            FROM
+                parcel_card_row
+            WINDOW
+                parcel_cards AS (
+                    PARTITION BY parcel_key, tax_year
+                    ORDER BY card NULLS LAST
+                    ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+                )
+        )
+        SELECT
+            * EXCLUDE (
+                parcel_key,
+                card_rank,
+                parcel_building_appraised,
+                parcel_dwelling_value,
+                parcel_other_building,
+                parcel_structure_area,
+                parcel_cubic_feet,
+                parcel_units,
+                parcel_total_rooms
+            ) REPLACE (
+                parcel_building_appraised AS building_appraised,
+                parcel_dwelling_value AS dwelling_value,
+                parcel_other_building AS other_building,
+                parcel_structure_area AS structure_area,
+                parcel_cubic_feet AS cubic_feet,
+                parcel_units AS units,
+                parcel_total_rooms AS total_rooms,
+                CASE
+                    WHEN land_appraised IS NULL AND parcel_building_appraised IS NULL THEN total_appraised
+                    ELSE COALESCE(land_appraised, 0) + COALESCE(parcel_building_appraised, 0)
+                END AS total_appraised
+            )
+        FROM
+            cards
+        WHERE
+            card_rank = 1;",
+        CARD_COLUMNS
+    ))
+}
+
+// Function to save records in chunks using multiple threads; `numbers` holds the number
+// each record is given in errors
+fn save_records_in_chunks(
+    conn: &Arc<Mutex<Database>>,
+    records: &[ParcelRecord],
+    numbers: &[usize],
+    run_id: Option<i64>,
+) -> Result<(), Error> {
     let mut threads = Vec::new();
 
-    for chunk in record_chunks {
-        let conn = Arc::clone(&conn);
+    for (chunk, numbers) in records.chunks(CHUNK_SIZE).zip(numbers.chunks(CHUNK_SIZE)) {
+        let conn = Arc::clone(conn);
         let chunk = chunk.to_vec();
+        let numbers = numbers.to_vec();
 
-        let handle = thread::spawn(move || save_record_chunk(&conn, &chunk));
+        let handle = thread::spawn(move || save_record_chunk(&conn, &chunk, &numbers, run_id));
         threads.push(handle);
     }
 
     for handle in threads {
-        handle.join().expect("Thread panicked");
+        handle.join().expect("Thread panicked")?;
     }
+    Ok(())
 }
 
-// Function to save a single chunk of records
-fn save_record_chunk(conn: &Arc<Mutex<Connection>>, chunk: &[ParcelRecord]) {
+// Function to save a single chunk of records, numbered by `numbers` in errors
+fn save_record_chunk(
+    conn: &Arc<Mutex<Database>>,
+    chunk: &[ParcelRecord],
+    numbers: &[usize],
+    run_id: Option<i64>,
+) -> Result<(), Error> {
     let conn = conn.lock().expect("Failed to acquire the lock on connection");
-    let mut appender = conn.appender("parcel").expect("Failed to create appender");
+    let mut appender = conn.appender("parcel").map_err(Error::append("parcel"))?;
 
-    for record in chunk {
-        let values = parcel_record_2_sql(record);
-        if let Err(e) = appender.append_row(&values[..]) {
-            // Early return to avoid poisoning the mutex
-            return;
-        }
+    for (record, &number) in chunk.iter().zip(numbers) {
+        let mut values = parcel_record_2_sql(record);
+        values.push(run_id.into());
+        appender.append_row(appender_params_from_iter(values)).map_err(|source| Error::Append {
+            table: "parcel".to_string(),
+            record: Some(number),
+            source: Box::new(source),
+        })?;
     }
 
-    if let Err(e) = appender.flush() {
-        eprintln!("Failed to flush appender: {:?}", e);
+    appender.flush().map_err(Error::append("parcel"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbf::tests::write_dbf;
+
+    // Five records: one deleted, one with a tax year that can't be decoded and one with a
+    // fractional tax year
+    fn write_test_dbf(name: &str) -> std::path::PathBuf {
+        let records = [("A", 2024.0), ("B", 2024.0), ("C", 2024.0), ("D", 2024.0), ("E", 2024.5)];
+        write_dbf(name, &records, &[1], &[3])
+    }
+
+    #[test]
+    fn records_the_run_and_its_rejects() {
+        let db = Database::in_memory().unwrap();
+        // A glob character in the name must not stop the file being hashed
+        let path = write_test_dbf("ingest[1]");
+        let run = load_dbf(&path, &db);
+        let expected_sha256 = file_sha256(&path);
+        fs::remove_file(&path).unwrap();
+        let run = run.unwrap();
+        assert_eq!(run.rows_loaded, 2);
+        assert_eq!(run.rejected.len(), 2);
+
+        let (sha256, num_records, loaded, rejected, status): (String, i64, i64, i64, String) = db
+            .query_row(
+                "SELECT source_sha256, dbf_num_records, rows_loaded, rows_rejected, status
+                FROM ingest_run WHERE run_id = ? AND finished_at IS NOT NULL AND error IS NULL",
+                [run.run_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(sha256, expected_sha256.unwrap());
+        assert_eq!(sha256.len(), 64);
+        assert_eq!((num_records, loaded, rejected, status.as_str()), (5, 2, 2, "loaded"));
+
+        let mut stmt = db.prepare("SELECT run_id, record, column_name FROM ingest_reject ORDER BY record").unwrap();
+        let rejects: Vec<(i64, i64, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(rejects, [(run.run_id, 3, "TaxYear".to_string()), (run.run_id, 5, "TaxYear".to_string())]);
+    }
+
+    #[test]
+    fn rolls_back_and_closes_a_failed_run() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch("DROP TABLE ingest_reject").unwrap();
+        let path = write_test_dbf("failed");
+        let run = load_dbf(&path, &db);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(run, Err(Error::Append { ref table, .. }) if table == "ingest_reject"));
+
+        let (loaded, status, error): (i64, String, Option<String>) = db
+            .query_row(
+                "SELECT rows_loaded, status, error FROM ingest_run WHERE finished_at IS NOT NULL",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((loaded, status.as_str()), (0, "failed"));
+        assert!(error.unwrap().contains("ingest_reject"));
+
+        // The rows appended before the failure went with the transaction
+        let parcels: i64 = db.query_row("SELECT COUNT(*) FROM parcel", [], |row| row.get(0)).unwrap();
+        assert_eq!(parcels, 0);
     }
 }
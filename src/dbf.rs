@@ -1,24 +1,379 @@
-use std::error::Error;
-use dbase::Reader;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use dbase::{Date, FieldType, Reader, ReaderBuilder, UnicodeLossy};
 use crate::data_mapper::map_record_to_parcel;
 use crate::parcel_record::ParcelRecord;
+use crate::error::Error;
+
+/// A parcel DBF file read into memory, with the header fields kept for provenance.
+pub struct DbfFile {
+    pub records: Vec<ParcelRecord>,
+    /// The 1-based place in the file of each of `records`, counting deleted records
+    pub record_numbers: Vec<usize>,
+    /// The records that couldn't be read, each as the error naming it; they are left out
+    /// of `records`
+    pub rejected: Vec<Error>,
+    /// The date the header says the file was last updated
+    pub last_update: Date,
+    /// The number of records the header counts, including deleted ones that aren't read
+    pub num_records: usize,
+}
+
+/// Reads every record of a parcel DBF file.
+///
+/// Fails with the path, and where it applies the record number and column, when the file
+/// is missing, corrupt or holds a field of the wrong type.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<ParcelRecord>, Error> {
+    let mut file = read_file(path)?;
+    if !file.rejected.is_empty() {
+        return Err(file.rejected.swap_remove(0));
+    }
+    Ok(file.records)
+}
+
+/// Reads every record of a parcel DBF file along with its header. Records marked deleted
+/// are skipped, and memo fields are read from the memo file beside it.
+///
+/// A record that can't be decoded, or holds a value that doesn't fit its `ParcelRecord`
+/// field, is rejected rather than failing the read; the file fails only when it can't be
+/// opened or its header can't be read.
+pub fn read_file(path: impl AsRef<Path>) -> Result<DbfFile, Error> {
+    let path = path.as_ref();
 
-pub fn read(path: String) -> duckdb::Result<Vec<ParcelRecord>, Box<dyn Error>> {
     // Open the DBF file
-    let mut reader = Reader::from_path(path)?;
+    let mut reader = open(path)?;
+    let header = *reader.header();
 
-    // Access the header
-    let header = reader.header();
-    println!("DBF Version: {:?}", header.last_update);
-    println!("Number of records: {}", header.num_records);
+    // The reader skips deleted records without saying so, so number the records it returns
+    // by their place in the file, and stop when they run out rather than reading on into
+    // whatever follows them
+    let record_numbers = live_record_numbers(
+        path,
+        header.offset_to_first_record.into(),
+        header.size_of_record.into(),
+        header.num_records as usize,
+    )?;
 
     // Read records and map them into ParcelRecord structs
     let mut records = Vec::new();
+    let mut numbers = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (&record_number, result) in record_numbers.iter().zip(reader.iter_records()) {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                rejected.push(Error::dbf(path, Some(record_number), e));
+                continue;
+            }
+        };
+        match map_record_to_parcel(&record) {
+            Ok(parcel_record) => {
+                records.push(parcel_record);
+                numbers.push(record_number);
+            }
+            Err(e) => rejected.push(Error::Mapping {
+                path: path.to_path_buf(),
+                record: record_number,
+                column: e.column,
+                message: e.message,
+            }),
+        }
+    }
+    Ok(DbfFile {
+        records,
+        record_numbers: numbers,
+        rejected,
+        last_update: header.last_update,
+        num_records: header.num_records as usize,
+    })
+}
+
+// Function to open a DBF file, with its memo file when it has memo fields.
+//
+// The memo file has the DBF's name and a .dbt extension, or .fpt for FoxPro files. It is
+// matched in any case, since exports made on Windows often have upper-case extensions
+// that the dbase crate's own lookup misses.
+fn open(path: &Path) -> Result<Reader<BufReader<File>>, Error> {
+    let open_file = |path: &Path| File::open(path).map(BufReader::new).map_err(|e| Error::file(path, e));
+    let reader = ReaderBuilder::<_, UnicodeLossy>::new(open_file(path)?)
+        .build()
+        .map_err(|e| Error::dbf(path, None, e))?;
+    if !reader.fields().iter().any(|field| field.field_type() == FieldType::Memo) {
+        return Ok(reader);
+    }
+
+    // FoxBase, Visual FoxPro and FoxPro 2 files keep their memos in .fpt files, and dBase
+    // ones in .dbt files; dbase doesn't export its version type, so read its byte
+    let extension = match u8::from(reader.header().file_type) {
+        0x02 | 0x30 | 0x31 | 0x32 | 0xF5 => "fpt",
+        _ => "dbt",
+    };
+    let memo_path = find_beside(path, extension).unwrap_or_else(|| path.with_extension(extension));
+    ReaderBuilder::<_, UnicodeLossy>::new(open_file(path)?)
+        .with_memo(open_file(&memo_path)?)
+        .build()
+        .map_err(|e| Error::dbf(path, None, e))
+}
+
+// Function to list the 1-based numbers of the records not marked deleted, in file order.
+// A file cut short of the records its header counts has its missing records left out.
+fn live_record_numbers(path: &Path, offset: u64, record_size: i64, count: usize) -> Result<Vec<usize>, Error> {
+    let mut source = BufReader::new(File::open(path).map_err(|e| Error::file(path, e))?);
+    source
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| Error::file(path, e))?;
+
+    let mut numbers = Vec::new();
+    let mut flag = [0u8];
+    for index in 0..count {
+        match source.read_exact(&mut flag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(Error::file(path, e)),
+        }
+        if flag[0] != b'*' {
+            numbers.push(index + 1);
+        }
+        source
+            .seek_relative(record_size - 1)
+            .map_err(|e| Error::file(path, e))?;
+    }
+    Ok(numbers)
+}
+
+// Function to find the file beside `path` with the same name and `extension` in any case
+fn find_beside(path: &Path, extension: &str) -> Option<PathBuf> {
+    let name = path.file_stem()?;
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::read_dir(dir).ok()?.filter_map(Result::ok).map(|entry| entry.path()).find(|candidate| {
+        candidate.file_stem() == Some(name)
+            && candidate.extension().and_then(OsStr::to_str).is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use dbase::{DateTime, FieldValue, Record, TableWriterBuilder, Time};
+    use rust_decimal::Decimal;
+
+    // Writes a DBF of (parcel ID, tax year) records, marking the records at `deleted`
+    // (1-based) as deleted and filling the tax year of those at `corrupt` with letters
+    pub(crate) fn write_dbf(name: &str, records: &[(&str, f64)], deleted: &[usize], corrupt: &[usize]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rust-real-e-{}-{}.dbf", name, std::process::id()));
+        let mut writer = TableWriterBuilder::new()
+            .add_character_field("ParcelID".try_into().unwrap(), 12)
+            .add_numeric_field("TaxYear".try_into().unwrap(), 6, 1)
+            .build_with_file_dest(&path)
+            .unwrap();
+        for (parcel_id, tax_year) in records {
+            let mut record = Record::default();
+            record.insert("ParcelID".to_string(), FieldValue::Character(Some(parcel_id.to_string())));
+            record.insert("TaxYear".to_string(), FieldValue::Numeric(Some(*tax_year)));
+            writer.write_record(&record).unwrap();
+        }
+        drop(writer);
+
+        let mut bytes = fs::read(&path).unwrap();
+        let offset = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let size = u16::from_le_bytes([bytes[10], bytes[11]]) as usize;
+        for number in deleted {
+            bytes[offset + (number - 1) * size] = b'*';
+        }
+        for number in corrupt {
+            let tax_year = offset + (number - 1) * size + 13;
+            bytes[tax_year..tax_year + 6].copy_from_slice(b"  abcd");
+        }
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    // Writes a DBF of version `version` with a ParcelID and a FloodRisks memo field, and a
+    // memo file beside it named `memo_name`. The first record's memo is block 1 of `memo`,
+    // and the second record's is blank.
+    fn write_memo_dbf(name: &str, version: u8, memo_name: &str, memo: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rust-real-e-{}-{}.dbf", name, std::process::id()));
+        let mut bytes = vec![version, 124, 1, 1];
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(97u16.to_le_bytes());
+        bytes.extend(23u16.to_le_bytes());
+        bytes.resize(32, 0);
+        for (field, field_type, length) in [("ParcelID", b'C', 12u8), ("FloodRisks", b'M', 10)] {
+            let mut descriptor = field.as_bytes().to_vec();
+            descriptor.resize(11, 0);
+            descriptor.push(field_type);
+            descriptor.extend([0, 0, 0, 0, length]);
+            descriptor.resize(32, 0);
+            bytes.extend(descriptor);
+        }
+        bytes.push(0x0D);
+        bytes.extend(b" A           ");
+        bytes.extend(b"         1");
+        bytes.extend(b" B           ");
+        bytes.extend(b"          ");
+        bytes.push(0x1A);
+        fs::write(&path, bytes).unwrap();
+        fs::write(dir.join(memo_name), memo).unwrap();
+        path
+    }
+
+    #[test]
+    fn skips_deleted_records() {
+        let path = write_dbf("deleted", &[("A", 2023.0), ("B", 2024.0), ("C", 2024.0)], &[1, 3], &[]);
+        let file = read_file(&path);
+        fs::remove_file(&path).unwrap();
+        let file = file.unwrap();
+        assert_eq!(file.num_records, 3);
+        assert_eq!(file.records.len(), 1);
+        assert_eq!(file.records[0].parcel_id.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn numbers_records_by_their_place_in_the_file() {
+        let path = write_dbf("numbering", &[("A", 2024.0), ("B", 2024.0), ("C", 2024.5)], &[1], &[]);
+        let file = read_file(&path);
+        let strict = read(&path);
+        fs::remove_file(&path).unwrap();
+        let file = file.unwrap();
+        assert_eq!(file.records.len(), 1);
+        assert_eq!(file.records[0].parcel_id.as_deref(), Some("B"));
+        assert_eq!(file.record_numbers, [2]);
+        match file.rejected.as_slice() {
+            [Error::Mapping { record, column, .. }] => assert_eq!((*record, column.as_str()), (3, "TaxYear")),
+            other => panic!("unexpected rejects: {:?}", other),
+        }
+        assert!(matches!(strict, Err(Error::Mapping { record: 3, .. })));
+    }
+
+    #[test]
+    fn rejects_records_that_cannot_be_decoded() {
+        let path = write_dbf("undecodable", &[("A", 2024.0), ("B", 2024.0), ("C", 2024.0)], &[1], &[2]);
+        let file = read_file(&path);
+        let strict = read(&path);
+        fs::remove_file(&path).unwrap();
+        let file = file.unwrap();
+        assert_eq!(file.records.len(), 1);
+        assert_eq!(file.records[0].parcel_id.as_deref(), Some("C"));
+        assert_eq!(file.record_numbers, [3]);
+        assert!(matches!(
+            file.rejected.as_slice(),
+            [Error::CorruptDbf { record: Some(2), column: Some(column), .. }] if column == "TaxYear"
+        ));
+        assert!(matches!(strict, Err(Error::CorruptDbf { record: Some(2), .. })));
+    }
+
+    #[test]
+    fn reads_dates_logicals_and_currency() {
+        let path = std::env::temp_dir().join(format!("rust-real-e-field-types-{}.dbf", std::process::id()));
+        let mut writer = TableWriterBuilder::new()
+            .add_character_field("ParcelID".try_into().unwrap(), 12)
+            .add_date_field("SaleDate".try_into().unwrap())
+            .add_logical_field("Commercial".try_into().unwrap())
+            .add_currency_field("TotalAppra".try_into().unwrap())
+            .build_with_file_dest(&path)
+            .unwrap();
+        // Currency is a count of ten-thousandths carried in the bits of a double
+        for (parcel_id, sale_date, commercial, ten_thousandths) in [
+            ("A", Some(Date::new(29, 2, 2024)), Some(true), 1_234_500i64),
+            ("B", None, Some(false), -50_000),
+            ("C", Some(Date::new(1, 1, 1900)), None, 0),
+        ] {
+            let mut record = Record::default();
+            record.insert("ParcelID".to_string(), FieldValue::Character(Some(parcel_id.to_string())));
+            record.insert("SaleDate".to_string(), FieldValue::Date(sale_date));
+            record.insert("Commercial".to_string(), FieldValue::Logical(commercial));
+            record.insert("TotalAppra".to_string(), FieldValue::Currency(f64::from_bits(ten_thousandths as u64)));
+            writer.write_record(&record).unwrap();
+        }
+        drop(writer);
+        let records = read(&path);
+        fs::remove_file(&path).unwrap();
+        let records = records.unwrap();
+
+        let fields = |record: &ParcelRecord| (record.sale_date, record.commercial, record.total_appraised);
+        assert_eq!(
+            records.iter().map(fields).collect::<Vec<_>>(),
+            [
+                (NaiveDate::from_ymd_opt(2024, 2, 29), Some(1), Some(Decimal::new(12345, 2))),
+                (None, Some(0), Some(Decimal::new(-5, 0))),
+                (NaiveDate::from_ymd_opt(1900, 1, 1), None, Some(Decimal::ZERO)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_date_of_a_datetime() {
+        let path = std::env::temp_dir().join(format!("rust-real-e-datetime-{}.dbf", std::process::id()));
+        let mut writer = TableWriterBuilder::new()
+            .add_character_field("ParcelID".try_into().unwrap(), 12)
+            .add_datetime_field("SaleDate".try_into().unwrap())
+            .build_with_file_dest(&path)
+            .unwrap();
+        for (parcel_id, date, time) in [
+            ("A", Date::new(31, 12, 1999), Time::new(23, 59, 59)),
+            ("B", Date::new(29, 2, 2000), Time::new(0, 0, 0)),
+        ] {
+            let mut record = Record::default();
+            record.insert("ParcelID".to_string(), FieldValue::Character(Some(parcel_id.to_string())));
+            record.insert("SaleDate".to_string(), FieldValue::DateTime(DateTime::new(date, time)));
+            writer.write_record(&record).unwrap();
+        }
+        drop(writer);
+        let records = read(&path);
+        fs::remove_file(&path).unwrap();
+        let records = records.unwrap();
+
+        assert_eq!(
+            records.iter().map(|record| record.sale_date).collect::<Vec<_>>(),
+            [NaiveDate::from_ymd_opt(1999, 12, 31), NaiveDate::from_ymd_opt(2000, 2, 29)]
+        );
+    }
+
+    #[test]
+    fn reads_memos_from_a_dbt_file_in_any_case() {
+        let stem = format!("rust-real-e-dbase-memo-{}", std::process::id());
+        let mut memo = vec![0u8; 512];
+        memo[..4].copy_from_slice(&2u32.to_le_bytes());
+        memo.extend(b"ZONE AE\x1A\x1A");
+        memo.resize(1024, 0);
+        let path = write_memo_dbf("dbase-memo", 0x83, &format!("{}.DBT", stem), &memo);
+        let records = read(&path);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_file_name(format!("{}.DBT", stem))).unwrap();
+        let records = records.unwrap();
+
+        assert_eq!(
+            records.iter().map(|record| record.flood_risks.as_deref()).collect::<Vec<_>>(),
+            [Some("ZONE AE"), None]
+        );
+    }
+
+    #[test]
+    fn reads_memos_from_an_fpt_file_in_any_case() {
+        let stem = format!("rust-real-e-foxpro-memo-{}", std::process::id());
+        // A FoxPro memo file counts its blocks in a big-endian header, and each block
+        // starts with its type and length
+        let mut memo = vec![0u8; 64];
+        memo[..4].copy_from_slice(&2u32.to_le_bytes());
+        memo[6..8].copy_from_slice(&64u16.to_be_bytes());
+        memo.extend(1u32.to_be_bytes());
+        memo.extend(10u32.to_be_bytes());
+        memo.extend(b"ZONE X500\0");
+        memo.resize(128, 0);
+        let path = write_memo_dbf("foxpro-memo", 0xF5, &format!("{}.Fpt", stem), &memo);
+        let records = read(&path);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_file_name(format!("{}.Fpt", stem))).unwrap();
+        let records = records.unwrap();
 
-    for result in reader.iter_records() {
-        let record = result?;
-        let parcel_record = map_record_to_parcel(&record)?;
-        records.push(parcel_record);
+        assert_eq!(
+            records.iter().map(|record| record.flood_risks.as_deref()).collect::<Vec<_>>(),
+            [Some("ZONE X500"), None]
+        );
     }
-    Ok(records)
 }
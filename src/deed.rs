@@ -0,0 +1,447 @@
+use std::collections::HashSet;
+use duckdb::types::Value;
+use duckdb::{params, params_from_iter, Connection, Row};
+use rust_decimal::Decimal;
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// Normalises a deed book or page number.
+///
+/// Spaces and leading zeros are dropped and a letter suffix is upper-cased, so `0123`
+/// becomes `123` and `45 a` becomes `45A`. Returns `None` for anything that isn't a
+/// non-zero number with an optional one- or two-letter suffix.
+pub fn normalise_deed_number(raw: &str) -> Option<String> {
+    let compact: String = raw
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let digits_end = compact
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(compact.len());
+    let (digits, suffix) = compact.split_at(digits_end);
+    let digits = digits.trim_start_matches('0');
+
+    if digits.is_empty() || suffix.len() > 2 || !suffix.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(format!("{}{}", digits, suffix))
+}
+
+/// A deed book/page pair as recorded on a parcel, with its normalised form when valid.
+struct DeedReference {
+    parcel_id: Option<String>,
+    tax_year: Option<i32>,
+    kind: &'static str,
+    owner: Option<String>,
+    raw_book: Option<String>,
+    raw_page: Option<String>,
+}
+
+/// Rebuilds the `deed_reference` table from the raw deed book/page columns.
+///
+/// Each parcel contributes a `current` reference from `deed_book`/`deed_page` and, when a
+/// transfer is pending, a `pending` one from `new_deed_book`/`new_deed_page`. A parcel's
+/// cards often repeat its deed with different padding, so each parcel keeps one reference
+/// per tax year, kind and normalised book and page. References that don't normalise are
+/// kept with `valid = false` so they can be cleaned up at source.
+pub fn build_deed_reference_table(db: &Connection) -> Result<(), Error> {
+    db.execute("DELETE FROM deed_reference", []).map_err(Error::append("deed_reference"))?;
+
+    // Collect the references first so reading and appending don't overlap
+    let mut stmt = db.prepare(
+        "SELECT DISTINCT
+            parcel_id,
+            tax_year,
+            full_owner_name,
+            deed_book,
+            deed_page,
+            new_owner,
+            new_deed_book,
+            new_deed_page
+        FROM
+            parcel",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut references = Vec::new();
+    while let Some(row) = rows.next()? {
+        let parcel_id: Option<String> = row.get(0)?;
+        let tax_year: Option<i32> = row.get(1)?;
+        let deed_book: Option<String> = row.get(3)?;
+        let deed_page: Option<String> = row.get(4)?;
+        let new_owner: Option<String> = row.get(5)?;
+        let new_deed_book: Option<String> = row.get(6)?;
+        let new_deed_page: Option<String> = row.get(7)?;
+
+        if has_text(&deed_book) || has_text(&deed_page) {
+            references.push(DeedReference {
+                parcel_id: parcel_id.clone(),
+                tax_year,
+                kind: "current",
+                owner: row.get(2)?,
+                raw_book: deed_book,
+                raw_page: deed_page,
+            });
+        }
+        if has_text(&new_owner) || has_text(&new_deed_book) || has_text(&new_deed_page) {
+            references.push(DeedReference {
+                parcel_id,
+                tax_year,
+                kind: "pending",
+                owner: new_owner,
+                raw_book: new_deed_book,
+                raw_page: new_deed_page,
+            });
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut appender = db.appender("deed_reference").map_err(Error::append("deed_reference"))?;
+    for reference in &references {
+        let book = reference.raw_book.as_deref().and_then(normalise_deed_number);
+        let page = reference.raw_page.as_deref().and_then(normalise_deed_number);
+        let valid = book.is_some() && page.is_some();
+        // Invalid references are told apart by their raw text, since they don't normalise
+        let (book_key, page_key) = if valid {
+            (book.clone(), page.clone())
+        } else {
+            (reference.raw_book.clone(), reference.raw_page.clone())
+        };
+        if !seen.insert((&reference.parcel_id, reference.tax_year, reference.kind, book_key, page_key)) {
+            continue;
+        }
+        appender.append_row(params![
+            reference.parcel_id,
+            reference.tax_year,
+            reference.kind,
+            reference.owner,
+            reference.raw_book,
+            reference.raw_page,
+            book,
+            page,
+            valid,
+        ])
+        .map_err(Error::append("deed_reference"))?;
+    }
+    appender.flush().map_err(Error::append("deed_reference"))?;
+
+    Ok(())
+}
+
+fn has_text(value: &Option<String>) -> bool {
+    value.as_deref().is_some_and(|v| !v.trim().is_empty())
+}
+
+/// A deed conveying more than one parcel.
+struct SharedDeedRow {
+    book: String,
+    page: String,
+    parcel_count: i64,
+    owners: Option<String>,
+    total_appraised: Option<Decimal>,
+}
+
+impl FromRow for SharedDeedRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(SharedDeedRow {
+            book: row.get("book")?,
+            page: row.get("page")?,
+            parcel_count: row.get("parcel_count")?,
+            owners: row.get("owners")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+        })
+    }
+}
+
+/// Returns deeds that convey more than one parcel, largest conveyances first.
+pub fn shared_deeds(db: &Database, limit: usize, filter: &ParcelFilter) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        WITH conveyances AS (
+            SELECT
+                d.book,
+                d.page,
+                d.parcel_id,
+                ANY_VALUE(d.owner) AS owner,
+                -- Counted once per parcel, however many references or years it has
+                ANY_VALUE(p.total_appraised) AS total_appraised
+            FROM
+                deed_reference d
+                JOIN parcel_summary p
+                    ON p.parcel_id = d.parcel_id
+                    AND p.tax_year IS NOT DISTINCT FROM d.tax_year
+            WHERE
+                d.kind = 'current'
+                AND d.valid
+            GROUP BY
+                d.book,
+                d.page,
+                d.parcel_id
+        )
+        SELECT
+            book,
+            page,
+            COUNT(*) AS parcel_count,
+            STRING_AGG(DISTINCT owner, '; ') AS owners,
+            SUM(total_appraised) AS total_appraised
+        FROM
+            conveyances
+        GROUP BY
+            book,
+            page
+        HAVING
+            COUNT(*) > 1
+        ORDER BY
+            parcel_count DESC,
+            total_appraised DESC
+        LIMIT
+            ?
+    ";
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.push(Value::BigInt(limit as i64));
+    let records: Vec<SharedDeedRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec!["Deed Book", "Deed Page", "Parcels", "Owners", "Total Appraised"];
+
+    // Callback to generate row data
+    let get_row_data = |record: &SharedDeedRow| -> Vec<Value> {
+        vec![
+            record.book.clone().into(),
+            record.page.clone().into(),
+            record.parcel_count.into(),
+            nullable(record.owners.clone()),
+            nullable_decimal(record.total_appraised),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+/// A parcel with a pending transfer, and the deeds on either side of it.
+struct PendingTransferRow {
+    parcel_id: Option<String>,
+    current_owner: Option<String>,
+    current_deed: Option<String>,
+    new_owner: Option<String>,
+    new_deed: Option<String>,
+}
+
+impl FromRow for PendingTransferRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(PendingTransferRow {
+            parcel_id: row.get("parcel_id")?,
+            current_owner: row.get("current_owner")?,
+            current_deed: row.get("current_deed")?,
+            new_owner: row.get("new_owner")?,
+            new_deed: row.get("new_deed")?,
+        })
+    }
+}
+
+/// Returns parcels with a pending transfer to a new owner.
+pub fn pending_transfers(db: &Database, limit: usize, filter: &ParcelFilter) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        SELECT DISTINCT
+            n.parcel_id,
+            c.owner AS current_owner,
+            CASE WHEN c.valid THEN c.book || '/' || c.page ELSE c.raw_book || '/' || c.raw_page END AS current_deed,
+            n.owner AS new_owner,
+            CASE WHEN n.valid THEN n.book || '/' || n.page ELSE n.raw_book || '/' || n.raw_page END AS new_deed
+        FROM
+            deed_reference n
+            LEFT JOIN deed_reference c
+                ON c.parcel_id = n.parcel_id
+                AND c.tax_year IS NOT DISTINCT FROM n.tax_year
+                AND c.kind = 'current'
+        WHERE
+            n.kind = 'pending'
+            AND EXISTS (
+                SELECT
+                    1
+                FROM
+                    parcel_summary p
+                WHERE
+                    p.parcel_id IS NOT DISTINCT FROM n.parcel_id
+                    AND p.tax_year IS NOT DISTINCT FROM n.tax_year
+            )
+        ORDER BY
+            n.parcel_id
+        LIMIT
+            ?
+    ";
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.push(Value::BigInt(limit as i64));
+    let records: Vec<PendingTransferRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec!["Parcel ID", "Current Owner", "Current Deed", "New Owner", "New Deed"];
+
+    // Callback to generate row data
+    let get_row_data = |record: &PendingTransferRow| -> Vec<Value> {
+        vec![
+            nullable(record.parcel_id.clone()),
+            nullable(record.current_owner.clone()),
+            nullable(record.current_deed.clone()),
+            nullable(record.new_owner.clone()),
+            nullable(record.new_deed.clone()),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+/// A tax year in which a parcel's deed changed.
+struct DeedChainRow {
+    tax_year: Option<i32>,
+    book: Option<String>,
+    page: Option<String>,
+    owner: Option<String>,
+}
+
+impl FromRow for DeedChainRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(DeedChainRow {
+            tax_year: row.get("tax_year")?,
+            book: row.get("book")?,
+            page: row.get("page")?,
+            owner: row.get("owner")?,
+        })
+    }
+}
+
+/// Returns the chain of deed references for a parcel across the loaded tax years,
+/// one row each time the deed changes.
+pub fn deed_chain(db: &Database, parcel_id: &str, filter: &ParcelFilter) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        WITH yearly AS (
+            SELECT
+                tax_year,
+                ANY_VALUE(book) AS book,
+                ANY_VALUE(page) AS page,
+                ANY_VALUE(owner) AS owner
+            FROM
+                deed_reference
+            WHERE
+                parcel_id = ?
+                AND kind = 'current'
+                AND valid
+                AND EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        parcel_summary p
+                    WHERE
+                        p.parcel_id = deed_reference.parcel_id
+                        AND p.tax_year IS NOT DISTINCT FROM deed_reference.tax_year
+                )
+            GROUP BY
+                tax_year
+        ),
+        changes AS (
+            SELECT
+                *,
+                LAG(book) OVER (ORDER BY tax_year) AS previous_book,
+                LAG(page) OVER (ORDER BY tax_year) AS previous_page
+            FROM
+                yearly
+        )
+        SELECT
+            tax_year,
+            book,
+            page,
+            owner
+        FROM
+            changes
+        WHERE
+            book IS DISTINCT FROM previous_book
+            OR page IS DISTINCT FROM previous_page
+        ORDER BY
+            tax_year
+    ";
+
+    // Restrict the query to the filtered parcels in every year, then run it
+    let (query, mut values) = filter.across_years().scope(query);
+    values.push(Value::Text(parcel_id.to_string()));
+    let records: Vec<DeedChainRow> = query_as(db, &query, params_from_iter(values))?;
+    if records.is_empty() {
+        return Err(Error::NotFound(format!("No valid deed references found for parcel ID '{}'", parcel_id)));
+    }
+
+    // Define headers for printing
+    let headers = vec!["First Tax Year", "Deed Book", "Deed Page", "Owner"];
+
+    // Callback to generate row data
+    let get_row_data = |record: &DeedChainRow| -> Vec<Value> {
+        vec![
+            nullable(record.tax_year),
+            nullable(record.book.clone()),
+            nullable(record.page.clone()),
+            nullable(record.owner.clone()),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalises_padding_zeros_and_suffixes() {
+        assert_eq!(normalise_deed_number("0123").as_deref(), Some("123"));
+        assert_eq!(normalise_deed_number(" 0123 ").as_deref(), Some("123"));
+        assert_eq!(normalise_deed_number("45 a").as_deref(), Some("45A"));
+        assert_eq!(normalise_deed_number("1 2 3").as_deref(), Some("123"));
+        assert_eq!(normalise_deed_number("77BC").as_deref(), Some("77BC"));
+    }
+
+    #[test]
+    fn rejects_blank_zero_and_malformed_numbers() {
+        assert_eq!(normalise_deed_number(""), None);
+        assert_eq!(normalise_deed_number("   "), None);
+        assert_eq!(normalise_deed_number("0000"), None);
+        assert_eq!(normalise_deed_number("ABC"), None);
+        assert_eq!(normalise_deed_number("12ABC"), None);
+        assert_eq!(normalise_deed_number("12-3"), None);
+        assert_eq!(normalise_deed_number("12A3"), None);
+    }
+
+    #[test]
+    fn counts_a_parcel_once_when_its_cards_pad_the_deed_differently() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_owner_name, deed_book, deed_page, land_appraised, building_appraised, total_appraised)
+            VALUES
+                (1, '02-01-0001', 2024, 1, 'SMITH', ' 0123 ', '45', 1000, 2000, 3000),
+                (2, '02-01-0001', 2024, 2, 'SMITH', '123', '0045', 1000, 1200, 2200),
+                (3, '02-01-0002', 2024, 1, 'SMITH', '123', '45', 2000, 2000, 4000);",
+        )
+        .unwrap();
+        build_deed_reference_table(&db).unwrap();
+
+        let references: i64 = db
+            .query_row("SELECT COUNT(*) FROM deed_reference WHERE parcel_id = '02-01-0001'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(references, 1);
+
+        let result = shared_deeds(&db, 10, &ParcelFilter::default()).unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0][2], Value::BigInt(2));
+        // Parcel 1 is 1,000 of land and 3,200 of buildings, parcel 2 is 4,000
+        assert_eq!(result.rows[0][4], nullable_decimal(Some(Decimal::new(8200, 0))));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use rustyline::error::ReadlineError;
+
+/// Errors returned by the library.
+///
+/// Ingestion errors say which file, record and column failed; record numbers count from 1.
+#[derive(Debug)]
+pub enum Error {
+    /// An input file doesn't exist
+    MissingFile { path: PathBuf },
+    /// A file could not be read or written
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// The DBF file is corrupt or can't be decoded
+    CorruptDbf {
+        path: PathBuf,
+        record: Option<usize>,
+        column: Option<String>,
+        source: Box<dbase::Error>,
+    },
+    /// A DBF field holds a value of the wrong type for its `ParcelRecord` field
+    Mapping {
+        path: PathBuf,
+        record: usize,
+        column: String,
+        message: String,
+    },
+    /// The database could not be opened
+    OpenDatabase { path: String, source: Box<duckdb::Error> },
+    /// A table or view could not be created
+    Schema { object: String, source: Box<duckdb::Error> },
+    /// Rows could not be appended to a table
+    Append {
+        table: String,
+        record: Option<usize>,
+        source: Box<duckdb::Error>,
+    },
+    /// The database was written by a newer build with a schema this one doesn't know
+    SchemaTooNew { path: String, version: i64, supported: i64 },
+    /// The database needs migrating but was opened read-only
+    SchemaOutdated { path: String, version: i64, current: i64 },
+    /// A schema migration failed; the database is left at the version before it
+    Migration {
+        version: i64,
+        description: String,
+        source: Box<duckdb::Error>,
+    },
+    /// A DuckDB query failed
+    Database(duckdb::Error),
+    /// A TOML file, such as distress rules, could not be parsed
+    Toml { path: Option<PathBuf>, source: Box<toml::de::Error> },
+    /// A result could not be written as JSON
+    Json(serde_json::Error),
+    /// The interactive shell could not read a line
+    Readline(ReadlineError),
+    /// A saved report or rule set is invalid
+    Config(String),
+    /// An argument, report parameter or filter value is invalid
+    InvalidInput(String),
+    /// A named report, parcel or table doesn't exist
+    NotFound(String),
+    /// A shapefile or stored parcel outline is malformed
+    Geometry(String),
+}
+
+impl Error {
+    /// Wraps an error reading or writing `path`, telling a missing file apart.
+    pub fn file(path: impl AsRef<Path>, source: io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => Error::MissingFile { path },
+            _ => Error::Io { path: Some(path), source },
+        }
+    }
+
+    /// Wraps a DBF read error from `path`, keeping the column the reader reports.
+    pub fn dbf(path: impl AsRef<Path>, record: Option<usize>, source: dbase::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        if let dbase::ErrorKind::IoError(e) = source.kind() {
+            if e.kind() == io::ErrorKind::NotFound {
+                return Error::MissingFile { path };
+            }
+        }
+        let column = source.field().as_ref().map(|field| field.name().to_string());
+        Error::CorruptDbf { path, record, column, source: Box::new(source) }
+    }
+
+    /// Wraps a failure creating the table or view `object`.
+    pub fn schema(object: &str) -> impl FnOnce(duckdb::Error) -> Self + '_ {
+        move |source| Error::Schema { object: object.to_string(), source: Box::new(source) }
+    }
+
+    /// Wraps a failure appending rows to `table`.
+    pub fn append(table: &str) -> impl FnOnce(duckdb::Error) -> Self + '_ {
+        move |source| Error::Append { table: table.to_string(), record: None, source: Box::new(source) }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingFile { path } => write!(f, "{} does not exist", path.display()),
+            Error::Io { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::CorruptDbf { path, record, column, source } => {
+                write!(f, "{}: corrupt DBF", path.display())?;
+                if let Some(record) = record {
+                    write!(f, " at record {}", record)?;
+                }
+                if let Some(column) = column {
+                    write!(f, ", column {}", column)?;
+                }
+                write!(f, ": {}", source)
+            }
+            Error::Mapping { path, record, column, message } => {
+                write!(f, "{}: record {}, column {}: {}", path.display(), record, column, message)
+            }
+            Error::OpenDatabase { path, source } => write!(f, "cannot open database {}: {}", path, source),
+            Error::Schema { object, source } => write!(f, "cannot create {}: {}", object, source),
+            Error::Append { table, record: Some(record), source } => {
+                write!(f, "cannot append record {} to {}: {}", record, table, source)
+            }
+            Error::Append { table, record: None, source } => write!(f, "cannot append to {}: {}", table, source),
+            Error::SchemaTooNew { path, version, supported } => write!(
+                f,
+                "{} has schema version {}, but this build only supports up to {}; upgrade to open it",
+                path, version, supported
+            ),
+            Error::SchemaOutdated { path, version, current } => write!(
+                f,
+                "{} has schema version {} and needs migrating to {}; open it once without read-only mode",
+                path, version, current
+            ),
+            Error::Migration { version, description, source } => {
+                write!(f, "schema migration {} ({}) failed: {}", version, description, source)
+            }
+            Error::Database(e) => write!(f, "database error: {}", e),
+            Error::Toml { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            Error::Toml { path: None, source } => write!(f, "{}", source),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Readline(e) => write!(f, "{}", e),
+            Error::Config(message)
+            | Error::InvalidInput(message)
+            | Error::NotFound(message)
+            | Error::Geometry(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::CorruptDbf { source, .. } => Some(source),
+            Error::OpenDatabase { source, .. }
+            | Error::Schema { source, .. }
+            | Error::Append { source, .. }
+            | Error::Migration { source, .. } => Some(source),
+            Error::Database(e) => Some(e),
+            Error::Toml { source, .. } => Some(source),
+            Error::Json(e) => Some(e),
+            Error::Readline(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+impl From<duckdb::Error> for Error {
+    fn from(e: duckdb::Error) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(source: toml::de::Error) -> Self {
+        Error::Toml { path: None, source: Box::new(source) }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<ReadlineError> for Error {
+    fn from(e: ReadlineError) -> Self {
+        Error::Readline(e)
+    }
+}
+
+// Parses a value typed by the user, such as a filter or report parameter
+pub(crate) fn parse_input<T: FromStr>(raw: &str) -> Result<T>
+where
+    T::Err: fmt::Display,
+{
+    raw.trim().parse().map_err(|e: T::Err| Error::InvalidInput(e.to_string()))
+}
+
+/// A `Result` whose error is the library's [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,204 @@
+use clap::Args;
+use duckdb::types::Value;
+use crate::error::{parse_input, Error};
+
+/// Filters that narrow any report to a subset of parcels.
+///
+/// `parcel_summary` holds a row per parcel for every tax year loaded, so reports only see
+/// the latest tax year unless another is chosen or every year is asked for. Every filter
+/// value is bound as a query parameter, never pasted into the SQL, so the options can come
+/// straight from the command line or a query string.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ParcelFilter {
+    /// Only include parcels in this district, by name or code
+    #[arg(long, global = true)]
+    pub district: Option<String>,
+    /// Only include parcels with this land use, e.g. RESIDENTIAL
+    #[arg(long, global = true)]
+    pub land_use: Option<String>,
+    /// Only include parcels appraised at this value or more
+    #[arg(long, global = true)]
+    pub min_value: Option<f64>,
+    /// Only include parcels appraised at this value or less
+    #[arg(long, global = true)]
+    pub max_value: Option<f64>,
+    /// Only include owners matching this case-insensitive LIKE pattern, e.g. '%LLC'
+    #[arg(long, global = true)]
+    pub owner: Option<String>,
+    /// Only include this tax year; the latest loaded is used when omitted
+    #[arg(long, global = true)]
+    pub tax_year: Option<i64>,
+    /// Include every loaded tax year instead of only the latest
+    #[arg(long, global = true, conflicts_with = "tax_year")]
+    pub all_years: bool,
+}
+
+impl ParcelFilter {
+    /// Sets the filter named `key` from a query-string value. Returns false when `key` is
+    /// not a filter, so the caller can treat it as something else.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<bool, Error> {
+        match key {
+            "district" => self.district = Some(value.to_string()),
+            "land_use" => self.land_use = Some(value.to_string()),
+            "min_value" => self.min_value = Some(parse_input(value)?),
+            "max_value" => self.max_value = Some(parse_input(value)?),
+            "owner" => self.owner = Some(value.to_string()),
+            "tax_year" => self.tax_year = Some(parse_input(value)?),
+            "all_years" => self.all_years = parse_input(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether no filter is set, so reports see every parcel in the latest tax year.
+    pub fn is_empty(&self) -> bool {
+        self.district.is_none()
+            && self.land_use.is_none()
+            && self.min_value.is_none()
+            && self.max_value.is_none()
+            && self.owner.is_none()
+            && self.tax_year.is_none()
+            && !self.all_years
+    }
+
+    /// The same filter over every loaded tax year, for reports that trace parcels through
+    /// time. A chosen tax year still applies.
+    pub fn across_years(&self) -> ParcelFilter {
+        ParcelFilter { all_years: true, ..self.clone() }
+    }
+
+    // Returns the conditions on `parcel_summary` and the values they bind, in order
+    fn conditions(&self) -> (Vec<&'static str>, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        if let Some(district) = &self.district {
+            conditions.push("(district_name ILIKE ? OR district_code = TRY_CAST(? AS INTEGER))");
+            values.push(Value::Text(district.clone()));
+            values.push(Value::Text(district.clone()));
+        }
+        if let Some(land_use) = &self.land_use {
+            conditions.push("land_use ILIKE ?");
+            values.push(Value::Text(land_use.clone()));
+        }
+        if let Some(min_value) = self.min_value {
+            conditions.push("total_appraised >= ?");
+            values.push(Value::Double(min_value));
+        }
+        if let Some(max_value) = self.max_value {
+            conditions.push("total_appraised <= ?");
+            values.push(Value::Double(max_value));
+        }
+        if let Some(owner) = &self.owner {
+            conditions.push("full_owner_name ILIKE ?");
+            values.push(Value::Text(owner.clone()));
+        }
+        match self.tax_year {
+            Some(tax_year) => {
+                conditions.push("tax_year = ?");
+                values.push(Value::BigInt(tax_year));
+            }
+            None if !self.all_years => {
+                conditions.push("tax_year IS NOT DISTINCT FROM (SELECT MAX(tax_year) FROM main.parcel)");
+            }
+            None => {}
+        }
+        (conditions, values)
+    }
+
+    /// Restricts a query to the matching parcels.
+    ///
+    /// A `parcel_summary` CTE holding only the matching rows is put in front of `sql`,
+    /// shadowing the view everywhere the query reads it. `sql` must use `?` placeholders;
+    /// the returned values bind the filter's and go before the query's own.
+    pub fn scope(&self, sql: &str) -> (String, Vec<Value>) {
+        let (conditions, values) = self.conditions();
+        if conditions.is_empty() {
+            return (sql.to_string(), values);
+        }
+
+        let cte = format!(
+            "parcel_summary AS (SELECT * FROM main.parcel_summary WHERE {})",
+            conditions.join(" AND ")
+        );
+        // Join the query's own CTEs rather than nesting a second WITH, keeping RECURSIVE at
+        // the front where it has to be
+        let trimmed = sql.trim_start();
+        let mut words = trimmed.split_whitespace();
+        let scoped = match (words.next(), words.next()) {
+            (Some(with), Some(recursive))
+                if with.eq_ignore_ascii_case("with") && recursive.eq_ignore_ascii_case("recursive") =>
+            {
+                let rest = trimmed[4..].trim_start();
+                format!("WITH RECURSIVE {},\n{}", cte, &rest[9..])
+            }
+            (Some(with), _) if with.eq_ignore_ascii_case("with") => {
+                format!("WITH {},\n{}", cte, &trimmed[4..])
+            }
+            _ => format!("WITH {}\n{}", cte, trimmed),
+        };
+        (scoped, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+
+    fn count(db: &Database, filter: &ParcelFilter) -> i64 {
+        let (sql, values) = filter.scope("SELECT COUNT(*) FROM parcel_summary");
+        db.query_row(&sql, duckdb::params_from_iter(values), |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn defaults_to_the_latest_tax_year() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, land_use)
+            VALUES
+                (1, '02-01-0001', 2023, 1, 'RESIDENTIAL'),
+                (2, '02-01-0002', 2023, 1, 'COMMERCIAL'),
+                (3, '02-01-0001', 2024, 1, 'RESIDENTIAL');",
+        )
+        .unwrap();
+
+        assert_eq!(count(&db, &ParcelFilter::default()), 1);
+        assert_eq!(count(&db, &ParcelFilter { tax_year: Some(2023), ..Default::default() }), 2);
+        assert_eq!(count(&db, &ParcelFilter::default().across_years()), 3);
+
+        let mut filter = ParcelFilter::default();
+        assert!(filter.set("all_years", "true").unwrap());
+        assert!(filter.set("land_use", "residential").unwrap());
+        assert!(!filter.set("limit", "5").unwrap());
+        assert_eq!(count(&db, &filter), 2);
+    }
+
+    #[test]
+    fn is_empty_only_without_filters() {
+        assert!(ParcelFilter::default().is_empty());
+        assert!(!ParcelFilter::default().across_years().is_empty());
+        assert!(!ParcelFilter { owner: Some("%LLC".to_string()), ..Default::default() }.is_empty());
+    }
+
+    #[test]
+    fn joins_the_query_own_ctes() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card)
+            VALUES (1, '02-01-0001', 2023, 1), (2, '02-01-0001', 2024, 1);",
+        )
+        .unwrap();
+        let filter = ParcelFilter::default();
+        let run = |sql: &str| -> i64 {
+            let (sql, values) = filter.scope(sql);
+            db.query_row(&sql, duckdb::params_from_iter(values), |row| row.get(0)).unwrap()
+        };
+
+        assert_eq!(run("with years AS (SELECT tax_year FROM parcel_summary) SELECT COUNT(*) FROM years"), 1);
+        assert_eq!(
+            run("WITH\n  Recursive n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 3)
+                SELECT COUNT(*) FROM parcel_summary, n"),
+            3
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use clap::ValueEnum;
+use duckdb::types::Value;
+use duckdb::{params, params_from_iter, Connection, Row};
+use rust_decimal::Decimal;
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// How the flood exposure report is broken down.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FloodBreakdown {
+    /// One row per flood zone
+    Zone,
+    /// One row per district and flood zone
+    District,
+    /// One row per land use and flood zone
+    LandUse,
+}
+
+impl FloodBreakdown {
+    /// The `parcel` column rows are grouped by alongside the zone, and its header.
+    fn column(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            FloodBreakdown::Zone => None,
+            FloodBreakdown::District => Some(("p.district_name", "District")),
+            FloodBreakdown::LandUse => Some(("p.land_use", "Land Use")),
+        }
+    }
+}
+
+/// Parses the free-text `flood_risks` field into FEMA flood zone designations.
+///
+/// Coded zones (`A`, `AE`, `A1`-`A30`, `VE`, `X`, ...) are only taken as whole words in a
+/// zone context: after `ZONE`, `ZONES` or `SHADED`, or when the text is nothing but a list
+/// of zones, such as `FLOODWAY, AE`. That keeps the article in "partially in a floodway"
+/// and FIRM panel numbers from reading as zones. The 0.2% annual chance zone is normalised
+/// to `X500` from `X500`/`B500`, "0.2 PCT/% annual chance" or "shaded X" wording, and
+/// regulatory floodways are recognised by name. Text that mentions no recognisable zone
+/// yields `UNKNOWN` so it still shows up in reports.
+pub fn parse_flood_zones(flood_risks: &str) -> Vec<String> {
+    let text = flood_risks
+        .trim()
+        .to_uppercase()
+        .replace('%', " PCT ")
+        .replace("PERCENT", "PCT");
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+        .map(|word| word.trim_matches('.'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    let mut zones: Vec<String> = Vec::new();
+    let mut push = |zone: String| {
+        if !zones.contains(&zone) {
+            zones.push(zone);
+        }
+    };
+
+    let is_list = words.iter().all(|word| normalise_zone(word).is_some() || LIST_WORDS.contains(word));
+    let mut in_zone = false;
+    for (i, word) in words.iter().enumerate() {
+        match *word {
+            "ZONE" | "ZONES" | "SHADED" => in_zone = true,
+            "AND" | "OR" if in_zone => {}
+            _ => match normalise_zone(word) {
+                Some(zone) if is_list || in_zone => {
+                    let before = &words[..i];
+                    let shaded = before.ends_with(&["SHADED"]) || before.ends_with(&["SHADED", "ZONE"]);
+                    push(if shaded && zone == "X" { "X500".to_string() } else { zone });
+                }
+                _ => in_zone = false,
+            },
+        }
+    }
+    // The shaded X zone and floodways are usually described rather than coded
+    if words.windows(4).any(|phrase| phrase == ["0.2", "PCT", "ANNUAL", "CHANCE"]) {
+        push("X500".to_string());
+    }
+    if words.contains(&"FLOODWAY") {
+        push("FLOODWAY".to_string());
+    }
+
+    if zones.is_empty() && !text.trim().is_empty() {
+        zones.push("UNKNOWN".to_string());
+    }
+    zones
+}
+
+// Words that can sit between zone codes in text that only lists zones
+const LIST_WORDS: &[&str] = &["FLOOD", "ZONE", "ZONES", "AND", "OR", "FLOODWAY"];
+
+fn normalise_zone(token: &str) -> Option<String> {
+    match token {
+        "A" | "AE" | "AH" | "AO" | "AR" | "A99" | "V" | "VE" | "B" | "C" | "D" | "X" => {
+            Some(token.to_string())
+        }
+        "X500" | "B500" => Some("X500".to_string()),
+        _ => {
+            // Older maps number their A and V zones, A1 through A30
+            let (prefix, number) = token.split_at(1.min(token.len()));
+            match (prefix, number.parse::<u8>()) {
+                ("A" | "V", Ok(1..=30)) if !number.starts_with('0') => Some(token.to_string()),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Whether a zone is part of the Special Flood Hazard Area, the 1% annual chance
+/// floodplain.
+pub fn is_special_flood_hazard_area(zone: &str) -> bool {
+    zone.starts_with('A') || zone.starts_with('V') || zone == "FLOODWAY"
+}
+
+/// Rebuilds the `parcel_flood_zone` table from the raw `flood_risks` text, one row per
+/// parcel and flood zone.
+pub fn build_flood_zone_table(db: &Connection) -> Result<(), Error> {
+    db.execute("DELETE FROM parcel_flood_zone", []).map_err(Error::append("parcel_flood_zone"))?;
+
+    // Collect the distinct flood texts first so reading and appending don't overlap
+    let mut stmt = db.prepare(
+        "SELECT DISTINCT
+            parcel_id,
+            tax_year,
+            flood_risks
+        FROM
+            parcel
+        WHERE
+            COALESCE(TRIM(flood_risks), '') <> ''",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let parcel_id: Option<String> = row.get(0)?;
+        let tax_year: Option<i32> = row.get(1)?;
+        let flood_risks: String = row.get(2)?;
+        records.push((parcel_id, tax_year, flood_risks));
+    }
+
+    let mut appender = db.appender("parcel_flood_zone").map_err(Error::append("parcel_flood_zone"))?;
+    for (parcel_id, tax_year, flood_risks) in &records {
+        for zone in parse_flood_zones(flood_risks) {
+            let sfha = is_special_flood_hazard_area(&zone);
+            appender.append_row(params![parcel_id, tax_year, zone, sfha])
+                .map_err(Error::append("parcel_flood_zone"))?;
+        }
+    }
+    appender.flush().map_err(Error::append("parcel_flood_zone"))?;
+
+    Ok(())
+}
+
+/// Parcel totals for one flood zone, within one breakdown group when there is one.
+struct FloodExposureRow {
+    breakdown: Option<String>,
+    zone: String,
+    sfha: bool,
+    parcel_count: i64,
+    acres: Option<Decimal>,
+    appraised: Option<Decimal>,
+}
+
+impl FromRow for FloodExposureRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(FloodExposureRow {
+            breakdown: row.get("breakdown")?,
+            zone: row.get("zone")?,
+            sfha: row.get("sfha")?,
+            parcel_count: row.get("parcel_count")?,
+            acres: get_decimal(row, "acres")?,
+            appraised: get_decimal(row, "appraised")?,
+        })
+    }
+}
+
+/// Returns parcel counts, acreage and appraised value per flood zone, optionally broken
+/// down further by district or land use.
+///
+/// A parcel mapped to several zones is counted once in each of them.
+pub fn flood_exposure(
+    db: &Database,
+    breakdown: FloodBreakdown,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query; the breakdown column comes from a fixed list, never from input
+    let group_column = breakdown.column().map_or("NULL", |(column, _)| column);
+    let query = format!(
+        "SELECT
+            {} AS breakdown,
+            z.zone,
+            z.sfha,
+            COUNT(*) AS parcel_count,
+            SUM(p.deeded_acre) AS acres,
+            SUM(p.total_appraised) AS appraised
+        FROM
+            parcel_flood_zone z
+            JOIN parcel_summary p
+                ON p.parcel_id = z.parcel_id
+                AND p.tax_year IS NOT DISTINCT FROM z.tax_year
+        GROUP BY
+            ALL
+        ORDER BY
+            breakdown,
+            appraised DESC",
+        group_column,
+    );
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, values) = filter.scope(&query);
+    let records: Vec<FloodExposureRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let mut headers = vec!["Zone", "SFHA", "Parcels", "Acres", "Total Appraised"];
+    if let Some((_, title)) = breakdown.column() {
+        headers.insert(0, title);
+    }
+
+    // Callback to generate row data
+    let get_row_data = |record: &FloodExposureRow| -> Vec<Value> {
+        let mut row = vec![
+            record.zone.clone().into(),
+            record.sfha.into(),
+            record.parcel_count.into(),
+            nullable_decimal(record.acres),
+            nullable_decimal(record.appraised),
+        ];
+        if breakdown.column().is_some() {
+            row.insert(0, nullable(record.breakdown.clone()));
+        }
+        row
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+/// An owner's parcels and appraised value, in total and inside the SFHA.
+struct OwnerFloodExposureRow {
+    full_owner_name: Option<String>,
+    parcel_count: i64,
+    sfha_parcel_count: i64,
+    total_appraised: Option<Decimal>,
+    sfha_appraised: Option<Decimal>,
+}
+
+impl FromRow for OwnerFloodExposureRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(OwnerFloodExposureRow {
+            full_owner_name: row.get("full_owner_name")?,
+            parcel_count: row.get("parcel_count")?,
+            sfha_parcel_count: row.get("sfha_parcel_count")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+            sfha_appraised: get_decimal(row, "sfha_appraised")?,
+        })
+    }
+}
+
+/// Returns the owners with the most appraised value inside the Special Flood Hazard Area.
+pub fn owner_flood_exposure(db: &Database, limit: usize, filter: &ParcelFilter) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        WITH at_risk AS (
+            SELECT DISTINCT
+                parcel_id,
+                tax_year
+            FROM
+                parcel_flood_zone
+            WHERE
+                sfha
+        )
+        SELECT
+            p.full_owner_name,
+            COUNT(*) AS parcel_count,
+            COUNT(r.parcel_id) AS sfha_parcel_count,
+            COALESCE(SUM(p.total_appraised), 0) AS total_appraised,
+            COALESCE(SUM(p.total_appraised) FILTER (WHERE r.parcel_id IS NOT NULL), 0) AS sfha_appraised
+        FROM
+            parcel_summary p
+            LEFT JOIN at_risk r
+                ON p.parcel_id = r.parcel_id
+                AND p.tax_year IS NOT DISTINCT FROM r.tax_year
+        GROUP BY
+            p.full_owner_name
+        HAVING
+            COUNT(r.parcel_id) > 0
+        ORDER BY
+            sfha_appraised DESC
+        LIMIT
+            ?
+    ";
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.push(Value::BigInt(limit as i64));
+    let records: Vec<OwnerFloodExposureRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec![
+        "Owner",
+        "Parcels",
+        "Parcels In SFHA",
+        "Total Appraised",
+        "Appraised In SFHA",
+        "Share At Risk %",
+    ];
+
+    // Callback to generate row data
+    let get_row_data = |record: &OwnerFloodExposureRow| -> Vec<Value> {
+        let mut share = match (record.sfha_appraised, record.total_appraised) {
+            (Some(at_risk), Some(total)) if total > Decimal::ZERO => at_risk / total * Decimal::ONE_HUNDRED,
+            _ => Decimal::ZERO,
+        };
+        share.rescale(2);
+        vec![
+            nullable(record.full_owner_name.clone()),
+            record.parcel_count.into(),
+            record.sfha_parcel_count.into(),
+            nullable_decimal(record.total_appraised),
+            nullable_decimal(record.sfha_appraised),
+            Value::Decimal(share),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::rebuild_derived_tables;
+
+    fn zones(text: &str) -> Vec<String> {
+        parse_flood_zones(text)
+    }
+
+    #[test]
+    fn reads_zone_lists_and_zone_context() {
+        assert_eq!(zones("Zone AE"), ["AE"]);
+        assert_eq!(zones("FLOOD ZONE X"), ["X"]);
+        assert_eq!(zones("ZONES A AND AE"), ["A", "AE"]);
+        assert_eq!(zones("FLOODWAY, AE"), ["AE", "FLOODWAY"]);
+        assert_eq!(zones("A; X500"), ["A", "X500"]);
+        assert_eq!(zones("X"), ["X"]);
+        assert_eq!(zones("a12"), ["A12"]);
+        assert_eq!(zones("MOSTLY IN ZONE AE, SOME ZONE X."), ["AE", "X"]);
+    }
+
+    #[test]
+    fn ignores_articles_and_words_outside_a_zone_context() {
+        assert_eq!(zones("PARTIALLY IN A FLOODWAY"), ["FLOODWAY"]);
+        assert_eq!(zones("SEE PANEL C FOR DETAILS"), ["UNKNOWN"]);
+        assert_eq!(zones("ZONE AE IN A FEW PLACES"), ["AE"]);
+    }
+
+    #[test]
+    fn does_not_read_panel_numbers_as_the_500_year_zone() {
+        assert_eq!(zones("54003C0500E"), ["UNKNOWN"]);
+        assert_eq!(zones("FIRM PANEL 54003C0500E ZONE X"), ["X"]);
+        assert_eq!(zones("500 FT FROM STREAM"), ["UNKNOWN"]);
+        assert_eq!(zones("A0"), ["UNKNOWN"]);
+    }
+
+    #[test]
+    fn reads_described_500_year_zone() {
+        assert_eq!(zones("0.2 PCT ANNUAL CHANCE FLOOD HAZARD"), ["X500"]);
+        assert_eq!(zones("0.2% annual chance flood hazard"), ["X500"]);
+        assert_eq!(zones("0.2 PERCENT ANNUAL CHANCE"), ["X500"]);
+        assert_eq!(zones("SHADED X"), ["X500"]);
+        assert_eq!(zones("Shaded Zone X"), ["X500"]);
+        assert_eq!(zones("B500"), ["X500"]);
+    }
+
+    #[test]
+    fn blank_text_has_no_zones() {
+        assert!(zones("").is_empty());
+        assert!(zones("  ").is_empty());
+    }
+
+    #[test]
+    fn special_flood_hazard_area_is_a_v_and_floodway() {
+        assert!(is_special_flood_hazard_area("AE"));
+        assert!(is_special_flood_hazard_area("VE"));
+        assert!(is_special_flood_hazard_area("FLOODWAY"));
+        assert!(!is_special_flood_hazard_area("X500"));
+        assert!(!is_special_flood_hazard_area("UNKNOWN"));
+    }
+
+    #[test]
+    fn totals_owner_exposure_in_exact_amounts() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_owner_name, flood_risks, total_appraised)
+            VALUES
+                (1, 'A', 2024, 1, 'SMITH', 'ZONE AE', 100.10),
+                (2, 'B', 2024, 1, 'SMITH', 'ZONE X', 200.20),
+                (3, 'C', 2024, 1, 'JONES', 'ZONE X', 5000);",
+        )
+        .unwrap();
+        rebuild_derived_tables(&db).unwrap();
+
+        let result = owner_flood_exposure(&db, 10, &ParcelFilter::default()).unwrap();
+        assert_eq!(
+            result.rows,
+            [vec![
+                Value::Text("SMITH".to_string()),
+                Value::BigInt(2),
+                Value::BigInt(1),
+                nullable_decimal(Some(Decimal::new(30030, 2))),
+                nullable_decimal(Some(Decimal::new(10010, 2))),
+                Value::Decimal(Decimal::new(3333, 2)),
+            ]]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use std::fs;
+use std::path::Path;
+use dbase::{FieldValue, Reader};
+use duckdb::{params, Connection};
+use crate::database::Database;
+use crate::error::Error;
+
+/// Half the width of the Web Mercator world, in metres.
+pub const WORLD_EXTENT: f64 = 20_037_508.342_789_244;
+
+// GRS80 ellipsoid, which NAD83 uses; WGS84 differs by well under a millimetre here
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_222_101;
+
+/// A closed ring of points, without the closing point repeated.
+pub type Ring = Vec<(f64, f64)>;
+
+/// An outer ring followed by its holes.
+pub type Polygon = Vec<Ring>;
+
+/// The outline of one parcel, which may be in several pieces.
+pub type MultiPolygon = Vec<Polygon>;
+
+/// The coordinate system shapefile points are in.
+#[derive(Debug, Clone, Copy)]
+pub enum SourceCrs {
+    /// Longitude and latitude in degrees
+    LonLat,
+    /// Northern-hemisphere UTM, in metres, on NAD83 or WGS84
+    Utm { zone: u8 },
+}
+
+impl SourceCrs {
+    /// Projects a point to Web Mercator metres.
+    pub fn to_web_mercator(self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (lon, lat) = match self {
+            SourceCrs::LonLat => (x, y),
+            SourceCrs::Utm { zone } => utm_to_lon_lat(zone, x, y),
+        };
+        let lat = lat.clamp(-85.051_128_78, 85.051_128_78).to_radians();
+        (
+            SEMI_MAJOR_AXIS * lon.to_radians(),
+            SEMI_MAJOR_AXIS * (std::f64::consts::FRAC_PI_4 + lat / 2.0).tan().ln(),
+        )
+    }
+}
+
+// Inverse transverse Mercator (Snyder, Map Projections: A Working Manual, 8-6 to 8-12)
+fn utm_to_lon_lat(zone: u8, easting: f64, northing: f64) -> (f64, f64) {
+    let k0 = 0.9996;
+    let e2 = FLATTENING * (2.0 - FLATTENING);
+    let ep2 = e2 / (1.0 - e2);
+    let x = easting - 500_000.0;
+
+    let mu = northing / k0 / (SEMI_MAJOR_AXIS * (1.0 - e2 / 4.0 - 3.0 * e2.powi(2) / 64.0 - 5.0 * e2.powi(3) / 256.0));
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    let phi1 = mu
+        + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+        + (21.0 * e1.powi(2) / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+        + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+        + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+    let sin_phi1 = phi1.sin();
+    let n1 = SEMI_MAJOR_AXIS / (1.0 - e2 * sin_phi1.powi(2)).sqrt();
+    let t1 = phi1.tan().powi(2);
+    let c1 = ep2 * phi1.cos().powi(2);
+    let r1 = SEMI_MAJOR_AXIS * (1.0 - e2) / (1.0 - e2 * sin_phi1.powi(2)).powf(1.5);
+    let d = x / (n1 * k0);
+
+    let lat = phi1
+        - (n1 * phi1.tan() / r1)
+            * (d.powi(2) / 2.0
+                - (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1.powi(2) - 9.0 * ep2) * d.powi(4) / 24.0
+                + (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1.powi(2) - 252.0 * ep2 - 3.0 * c1.powi(2))
+                    * d.powi(6)
+                    / 720.0);
+    let lon = (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0
+        + (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1.powi(2) + 8.0 * ep2 + 24.0 * t1.powi(2)) * d.powi(5) / 120.0)
+        / phi1.cos();
+
+    let central_meridian = f64::from(zone) * 6.0 - 183.0;
+    (central_meridian + lon.to_degrees(), lat.to_degrees())
+}
+
+/// Twice the signed area of a ring; positive when the points run anticlockwise with the
+/// y axis pointing up.
+pub fn signed_area(ring: &[(f64, f64)]) -> f64 {
+    let mut area = 0.0;
+    for (i, (x1, y1)) in ring.iter().enumerate() {
+        let (x2, y2) = ring[(i + 1) % ring.len()];
+        area += x1 * y2 - x2 * y1;
+    }
+    area
+}
+
+/// The centroid of an outline, weighting each piece by its area and taking holes out.
+/// Returns `None` for an outline with no area.
+pub fn centroid(outline: &MultiPolygon) -> Option<(f64, f64)> {
+    let (mut area, mut x, mut y) = (0.0, 0.0, 0.0);
+    // Holes wind the other way to their outer ring, so their area subtracts
+    for ring in outline.iter().flatten() {
+        for (i, (x1, y1)) in ring.iter().enumerate() {
+            let (x2, y2) = ring[(i + 1) % ring.len()];
+            let cross = x1 * y2 - x2 * y1;
+            area += cross;
+            x += (x1 + x2) * cross;
+            y += (y1 + y2) * cross;
+        }
+    }
+    (area != 0.0).then(|| (x / (3.0 * area), y / (3.0 * area)))
+}
+
+/// Reads the polygons of an ESRI shapefile, one entry per record in file order. Records
+/// with no shape give an empty outline.
+///
+/// Shapefiles draw outer rings clockwise and holes anticlockwise, so every clockwise ring
+/// starts a new polygon and the holes after it belong to it.
+pub fn read_shapefile_polygons(path: &Path) -> Result<Vec<MultiPolygon>, Error> {
+    let bytes = fs::read(path).map_err(|e| Error::file(path, e))?;
+    if bytes.len() < 100 || be_i32(&bytes, 0) != 9994 {
+        return Err(Error::Geometry(format!("{} is not a shapefile", path.display())));
+    }
+
+    let mut outlines = Vec::new();
+    let mut offset = 100;
+    while offset + 8 <= bytes.len() {
+        let record_number = be_i32(&bytes, offset);
+        let past_end = || Error::Geometry(format!("shapefile record {} runs past the end of the file", record_number));
+        // Lengths are counted in 16-bit words; a negative one is corrupt
+        let length = usize::try_from(be_i32(&bytes, offset + 4))
+            .map_err(|_| Error::Geometry(format!("shapefile record {} has a negative length", record_number)))?
+            * 2;
+        let end = (offset + 8).checked_add(length).filter(|end| *end <= bytes.len()).ok_or_else(past_end)?;
+        let content = &bytes[offset + 8..end];
+        offset = end;
+        if content.len() < 4 {
+            return Err(Error::Geometry(format!("shapefile record {} is truncated", record_number)));
+        }
+
+        let shape_type = le_i32(content, 0);
+        match shape_type {
+            0 => outlines.push(MultiPolygon::new()),
+            // Polygon, PolygonZ and PolygonM share the layout up to the points
+            5 | 15 | 25 => outlines.push(read_polygon_record(content).ok_or_else(|| {
+                Error::Geometry(format!("shapefile record {} is truncated", record_number))
+            })?),
+            other => {
+                return Err(Error::Geometry(format!(
+                    "shapefile record {} has shape type {}; only polygons are supported",
+                    record_number, other
+                )))
+            }
+        }
+    }
+    Ok(outlines)
+}
+
+fn read_polygon_record(content: &[u8]) -> Option<MultiPolygon> {
+    let part_count = usize::try_from(le_i32(content.get(..44)?, 36)).ok()?;
+    let point_count = usize::try_from(le_i32(content, 40)).ok()?;
+    let points_start = part_count.checked_mul(4)?.checked_add(44)?;
+    if content.len() < point_count.checked_mul(16)?.checked_add(points_start)? {
+        return None;
+    }
+
+    let mut starts: Vec<usize> = (0..part_count)
+        .map(|i| le_i32(content, 44 + 4 * i) as usize)
+        .collect();
+    starts.push(point_count);
+
+    let mut outline = MultiPolygon::new();
+    for part in starts.windows(2) {
+        let mut ring: Ring = (part[0]..part[1].min(point_count))
+            .map(|i| {
+                let at = points_start + 16 * i;
+                (le_f64(content, at), le_f64(content, at + 8))
+            })
+            .collect();
+        // Shapefile rings repeat their first point at the end
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+        if ring.len() < 3 {
+            continue;
+        }
+        match outline.last_mut() {
+            Some(polygon) if signed_area(&ring) > 0.0 => polygon.push(ring),
+            _ => outline.push(vec![ring]),
+        }
+    }
+    Some(outline)
+}
+
+fn be_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes(bytes[at..at + 4].try_into().expect("slice is four bytes"))
+}
+
+fn le_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes(bytes[at..at + 4].try_into().expect("slice is four bytes"))
+}
+
+fn le_f64(bytes: &[u8], at: usize) -> f64 {
+    f64::from_le_bytes(bytes[at..at + 8].try_into().expect("slice is eight bytes"))
+}
+
+/// Encodes an outline as little-endian WKB: a MultiPolygon, with rings closed.
+pub fn to_wkb(outline: &MultiPolygon) -> Vec<u8> {
+    let mut wkb = vec![1];
+    wkb.extend(6u32.to_le_bytes());
+    wkb.extend((outline.len() as u32).to_le_bytes());
+    for polygon in outline {
+        wkb.push(1);
+        wkb.extend(3u32.to_le_bytes());
+        wkb.extend((polygon.len() as u32).to_le_bytes());
+        for ring in polygon {
+            wkb.extend((ring.len() as u32 + 1).to_le_bytes());
+            for (x, y) in ring.iter().chain(ring.first()) {
+                wkb.extend(x.to_le_bytes());
+                wkb.extend(y.to_le_bytes());
+            }
+        }
+    }
+    wkb
+}
+
+/// Decodes an outline written by [`to_wkb`].
+pub fn from_wkb(wkb: &[u8]) -> Result<MultiPolygon, Error> {
+    let mut reader = WkbReader { wkb, at: 0 };
+    reader.header(6)?;
+    let mut outline = MultiPolygon::new();
+    for _ in 0..reader.u32()? {
+        reader.header(3)?;
+        let mut polygon = Polygon::new();
+        for _ in 0..reader.u32()? {
+            let mut ring: Ring = (0..reader.u32()?)
+                .map(|_| Ok((reader.f64()?, reader.f64()?)))
+                .collect::<Result<_, Error>>()?;
+            ring.pop();
+            polygon.push(ring);
+        }
+        outline.push(polygon);
+    }
+    Ok(outline)
+}
+
+struct WkbReader<'a> {
+    wkb: &'a [u8],
+    at: usize,
+}
+
+impl WkbReader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], Error> {
+        let bytes = self.wkb.get(self.at..self.at + count).ok_or_else(|| Error::Geometry("WKB is truncated".to_string()))?;
+        self.at += count;
+        Ok(bytes)
+    }
+
+    // Only little-endian geometries of the expected type are written, so only those are read
+    fn header(&mut self, geometry_type: u32) -> Result<(), Error> {
+        if self.take(1)? != [1] || self.u32()? != geometry_type {
+            return Err(Error::Geometry("unsupported WKB geometry".to_string()));
+        }
+        Ok(())
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("take returns the bytes asked for")))
+    }
+
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().expect("take returns the bytes asked for")))
+    }
+}
+
+/// Whether any parcel outlines have been loaded.
+pub fn has_parcel_geometry(conn: &Connection) -> duckdb::Result<bool> {
+    conn.query_row("SELECT COUNT(*) > 0 FROM parcel_geometry", [], |row| row.get(0))
+}
+
+/// Loads parcel outlines from a shapefile into the `parcel_geometry` table, replacing any
+/// loaded before.
+///
+/// Outlines are stored in Web Mercator with their bounding box and centroid. Each is matched to a parcel
+/// by the `id_field` column of the shapefile's `.dbf`, which may hold either the formatted
+/// parcel ID or the clean one. Returns the number of outlines loaded and how many matched.
+pub fn load_parcel_geometry(
+    db: &Database,
+    shapefile: &Path,
+    id_field: &str,
+    crs: SourceCrs,
+) -> Result<(usize, usize), Error> {
+    let outlines = read_shapefile_polygons(shapefile)?;
+    let dbf_path = shapefile.with_extension("dbf");
+    let mut reader = Reader::from_path(&dbf_path).map_err(|e| Error::dbf(&dbf_path, None, e))?;
+    let mut ids = Vec::new();
+    for (index, record) in reader.iter_records().enumerate() {
+        let record = record.map_err(|e| Error::dbf(&dbf_path, Some(index + 1), e))?;
+        let id = match record.get(id_field) {
+            Some(FieldValue::Character(value)) => value.clone(),
+            Some(FieldValue::Numeric(value)) => value.map(|v| v.to_string()),
+            Some(other) => Some(other.to_string()),
+            None => return Err(Error::InvalidInput(format!("the shapefile has no field named {}", id_field))),
+        };
+        ids.push(id.map(|id| id.trim().to_string()));
+    }
+    if ids.len() != outlines.len() {
+        return Err(Error::Geometry(format!(
+            "the shapefile has {} shapes but its .dbf has {} records",
+            outlines.len(),
+            ids.len()
+        )));
+    }
+
+    db.execute("DELETE FROM parcel_geometry", []).map_err(Error::append("parcel_geometry"))?;
+
+    let mut appender = db.appender("parcel_geometry").map_err(Error::append("parcel_geometry"))?;
+    let mut loaded = 0;
+    for (index, (id, outline)) in ids.iter().zip(outlines).enumerate() {
+        if outline.is_empty() {
+            continue;
+        }
+        let outline: MultiPolygon = outline
+            .into_iter()
+            .map(|polygon| {
+                polygon
+                    .into_iter()
+                    .map(|ring| ring.into_iter().map(|point| crs.to_web_mercator(point)).collect())
+                    .collect()
+            })
+            .collect();
+        let points = outline.iter().flatten().flatten();
+        let (min_x, min_y, max_x, max_y) = points.fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(min_x, min_y, max_x, max_y), (x, y)| (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y)),
+        );
+        let (centroid_x, centroid_y) = centroid(&outline).unwrap_or(((min_x + max_x) / 2.0, (min_y + max_y) / 2.0));
+        appender
+            .append_row(params![id, None::<String>, min_x, min_y, max_x, max_y, to_wkb(&outline), centroid_x, centroid_y])
+            .map_err(|source| Error::Append {
+                table: "parcel_geometry".to_string(),
+                record: Some(index + 1),
+                source: Box::new(source),
+            })?;
+        loaded += 1;
+    }
+    appender.flush().map_err(Error::append("parcel_geometry"))?;
+    drop(appender);
+
+    // Match outlines to parcels by either form of the parcel ID
+    let matched = db.execute(
+        "UPDATE parcel_geometry g
+        SET parcel_id = p.parcel_id
+        FROM (SELECT DISTINCT parcel_id, clean_parcel FROM parcel) p
+        WHERE g.source_id = p.parcel_id OR g.source_id = p.clean_parcel",
+        [],
+    )?;
+    Ok((loaded, matched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A clockwise square, as shapefiles draw outer rings
+    fn square(x: f64, y: f64, size: f64) -> Ring {
+        vec![(x, y), (x, y + size), (x + size, y + size), (x + size, y)]
+    }
+
+    // Content of a polygon record with one part per ring, each closed as shapefiles store them
+    fn polygon_content(rings: &[Ring]) -> Vec<u8> {
+        let point_count: usize = rings.iter().map(|ring| ring.len() + 1).sum();
+        let mut content = Vec::new();
+        content.extend(5i32.to_le_bytes());
+        content.extend([0u8; 32]);
+        content.extend((rings.len() as i32).to_le_bytes());
+        content.extend((point_count as i32).to_le_bytes());
+        let mut start = 0;
+        for ring in rings {
+            content.extend((start as i32).to_le_bytes());
+            start += ring.len() + 1;
+        }
+        for ring in rings {
+            for (x, y) in ring.iter().chain(ring.first()) {
+                content.extend(x.to_le_bytes());
+                content.extend(y.to_le_bytes());
+            }
+        }
+        content
+    }
+
+    // Writes a shapefile of records given as (length in 16-bit words, content)
+    fn write_shapefile(name: &str, records: &[(i32, Vec<u8>)]) -> std::path::PathBuf {
+        let mut bytes = vec![0u8; 100];
+        bytes[..4].copy_from_slice(&9994i32.to_be_bytes());
+        for (number, (length, content)) in records.iter().enumerate() {
+            bytes.extend((number as i32 + 1).to_be_bytes());
+            bytes.extend(length.to_be_bytes());
+            bytes.extend(content);
+        }
+        let path = std::env::temp_dir().join(format!("rust-real-e-{}-{}.shp", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_polygon_with_hole() {
+        let outer = square(0.0, 0.0, 10.0);
+        let mut hole = square(2.0, 2.0, 2.0);
+        hole.reverse();
+        let content = polygon_content(&[outer.clone(), hole.clone()]);
+        let path = write_shapefile("polygon", &[(content.len() as i32 / 2, content)]);
+        let outlines = read_shapefile_polygons(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(outlines.unwrap(), vec![vec![vec![outer, hole]]]);
+    }
+
+    #[test]
+    fn rejects_negative_record_length() {
+        let path = write_shapefile("negative", &[(-4, vec![0; 8])]);
+        let result = read_shapefile_polygons(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Geometry(message)) if message.contains("negative length")));
+    }
+
+    #[test]
+    fn rejects_record_running_past_the_end() {
+        let path = write_shapefile("past-end", &[(i32::MAX, vec![0; 8])]);
+        let result = read_shapefile_polygons(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Geometry(message)) if message.contains("past the end")));
+    }
+
+    #[test]
+    fn rejects_huge_part_and_point_counts() {
+        let mut content = polygon_content(&[square(0.0, 0.0, 1.0)]);
+        content[36..40].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert_eq!(read_polygon_record(&content), None);
+
+        let mut content = polygon_content(&[square(0.0, 0.0, 1.0)]);
+        content[40..44].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert_eq!(read_polygon_record(&content), None);
+    }
+
+    #[test]
+    fn centroid_weights_pieces_and_removes_holes() {
+        assert_eq!(centroid(&vec![vec![square(0.0, 0.0, 2.0)]]), Some((1.0, 1.0)));
+
+        // A piece four times the area pulls the centroid four fifths of the way to it
+        let outline = vec![vec![square(0.0, 0.0, 1.0)], vec![square(10.0, 0.0, 2.0)]];
+        let (x, _) = centroid(&outline).unwrap();
+        assert!((x - (0.5 + 0.8 * 10.5)).abs() < 1e-9);
+
+        // A unit hole centred on (2.5, 1.5) takes its share out of the square's (2, 2)
+        let mut hole = square(2.0, 1.0, 1.0);
+        hole.reverse();
+        let (x, y) = centroid(&vec![vec![square(0.0, 0.0, 4.0), hole]]).unwrap();
+        assert!((x - (16.0 * 2.0 - 2.5) / 15.0).abs() < 1e-9);
+        assert!((y - (16.0 * 2.0 - 1.5) / 15.0).abs() < 1e-9);
+
+        assert_eq!(centroid(&vec![vec![vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]]]), None);
+    }
+
+    #[test]
+    fn wkb_round_trips() {
+        let outline = vec![vec![square(0.0, 0.0, 10.0), square(2.0, 2.0, 2.0)], vec![square(20.0, 0.0, 5.0)]];
+        assert_eq!(from_wkb(&to_wkb(&outline)).unwrap(), outline);
+    }
+
+    #[test]
+    fn wkb_rejects_truncated_and_foreign_geometry() {
+        let wkb = to_wkb(&vec![vec![square(0.0, 0.0, 10.0)]]);
+        assert!(from_wkb(&wkb[..wkb.len() - 3]).is_err());
+
+        // A big-endian header is never written, so it isn't read
+        let mut big_endian = wkb.clone();
+        big_endian[0] = 0;
+        assert!(from_wkb(&big_endian).is_err());
+    }
+
+    #[test]
+    fn projects_lon_lat_to_web_mercator() {
+        let (x, y) = SourceCrs::LonLat.to_web_mercator((180.0, 0.0));
+        assert!((x - WORLD_EXTENT).abs() < 1e-6);
+        assert!(y.abs() < 1e-6);
+    }
+}
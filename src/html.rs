@@ -0,0 +1,273 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use duckdb::types::Value;
+use crate::output::{format_value, ResultSet};
+use crate::pretty_print::display_value;
+use crate::error::Error;
+
+/// A horizontal bar chart drawn from two columns of a section's result set.
+pub struct Chart {
+    /// Column holding the bar labels
+    pub label_column: usize,
+    /// Column holding the bar lengths
+    pub value_column: usize,
+}
+
+/// One analysis in the report: a heading, its table and optionally a chart.
+pub struct Section {
+    pub title: String,
+    /// Explanation shown under the heading
+    pub description: Option<String>,
+    pub result: ResultSet,
+    pub chart: Option<Chart>,
+}
+
+/// A self-contained HTML report with summary cards and a section per analysis.
+///
+/// The styles, the table-sorting script and the charts are all inline, so the file can be
+/// emailed or opened from disk without network access.
+pub struct HtmlReport {
+    pub title: String,
+    /// Headline figures shown as cards above the sections
+    pub cards: Vec<(String, String)>,
+    pub sections: Vec<Section>,
+}
+
+impl HtmlReport {
+    pub fn new(title: &str) -> Self {
+        HtmlReport {
+            title: title.to_string(),
+            cards: Vec::new(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Adds a card for every column of the first row of `result`.
+    pub fn add_cards(&mut self, result: &ResultSet) {
+        if let Some(row) = result.rows.first() {
+            for (i, (header, value)) in result.headers.iter().zip(row).enumerate() {
+                self.cards
+                    .push((header.clone(), display_value(result.column_format(i), value)));
+            }
+        }
+    }
+
+    pub fn add_section(
+        &mut self,
+        title: &str,
+        description: Option<&str>,
+        result: ResultSet,
+        chart: Option<Chart>,
+    ) {
+        self.sections.push(Section {
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            result,
+            chart,
+        });
+    }
+
+    /// Writes the report to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.render()).map_err(|e| Error::file(path, e))
+    }
+
+    /// Renders the report as a complete HTML document.
+    pub fn render(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(&self.title),
+            STYLE,
+            escape(&self.title),
+        );
+
+        if !self.cards.is_empty() {
+            html.push_str("<div class=\"cards\">\n");
+            for (label, value) in &self.cards {
+                let _ = writeln!(
+                    html,
+                    "<div class=\"card\"><div class=\"value\">{}</div><div class=\"label\">{}</div></div>",
+                    escape(value),
+                    escape(label),
+                );
+            }
+            html.push_str("</div>\n");
+        }
+
+        for section in &self.sections {
+            let _ = writeln!(html, "<section>\n<h2>{}</h2>", escape(&section.title));
+            if let Some(description) = &section.description {
+                let _ = writeln!(html, "<p class=\"description\">{}</p>", escape(description));
+            }
+            if let Some(chart) = &section.chart {
+                html.push_str(&render_chart(&section.result, chart));
+            }
+            html.push_str(&render_table(&section.result));
+            html.push_str("</section>\n");
+        }
+
+        let _ = write!(html, "<script>{}</script>\n</body>\n</html>\n", SORT_SCRIPT);
+        html
+    }
+}
+
+fn render_table(result: &ResultSet) -> String {
+    let mut html = String::from("<table class=\"sortable\">\n<thead><tr>");
+    for header in &result.headers {
+        let _ = write!(html, "<th>{}</th>", escape(header));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in &result.rows {
+        html.push_str("<tr>");
+        for (i, value) in row.iter().enumerate() {
+            // Numeric cells carry their raw value so sorting isn't thrown by formatting
+            match as_f64(value) {
+                Some(number) => {
+                    let _ = write!(
+                        html,
+                        "<td class=\"number\" data-sort=\"{}\">{}</td>",
+                        number,
+                        escape(&display_value(result.column_format(i), value))
+                    );
+                }
+                None => {
+                    let _ = write!(html, "<td>{}</td>", escape(&format_value(value)));
+                }
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+fn render_chart(result: &ResultSet, chart: &Chart) -> String {
+    const BAR_HEIGHT: usize = 22;
+    const LABEL_WIDTH: usize = 320;
+    const BAR_WIDTH: f64 = 480.0;
+
+    let bars: Vec<(String, f64)> = result
+        .rows
+        .iter()
+        .filter_map(|row| {
+            let value = as_f64(row.get(chart.value_column)?)?;
+            let label = row.get(chart.label_column).map(format_value).unwrap_or_default();
+            Some((label, value.max(0.0)))
+        })
+        .collect();
+    let max = bars.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    if bars.is_empty() || max <= 0.0 {
+        return String::new();
+    }
+
+    let height = bars.len() * BAR_HEIGHT;
+    let mut svg = format!(
+        "<svg class=\"chart\" viewBox=\"0 0 {} {}\" width=\"100%\" role=\"img\">\n",
+        LABEL_WIDTH as f64 + BAR_WIDTH + 120.0,
+        height
+    );
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let y = i * BAR_HEIGHT;
+        let width = value / max * BAR_WIDTH;
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\
+             <rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\"/>\
+             <text x=\"{:.1}\" y=\"{}\">{}</text>",
+            LABEL_WIDTH - 8,
+            y + 15,
+            escape(&truncate(label, 40)),
+            LABEL_WIDTH,
+            y + 3,
+            width,
+            BAR_HEIGHT - 6,
+            LABEL_WIDTH as f64 + width + 6.0,
+            y + 15,
+            escape(&display_value(result.column_format(chart.value_column), &Value::Double(*value))),
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::TinyInt(v) => Some(*v as f64),
+        Value::SmallInt(v) => Some(*v as f64),
+        Value::Int(v) => Some(*v as f64),
+        Value::BigInt(v) => Some(*v as f64),
+        Value::UTinyInt(v) => Some(*v as f64),
+        Value::USmallInt(v) => Some(*v as f64),
+        Value::UInt(v) => Some(*v as f64),
+        Value::UBigInt(v) => Some(*v as f64),
+        Value::Float(v) => Some(*v as f64),
+        Value::Double(v) => Some(*v),
+        Value::Decimal(v) => v.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+h1 { margin-bottom: 1rem; }
+h2 { margin-top: 2.5rem; border-bottom: 1px solid #ddd; padding-bottom: .3rem; }
+.description { color: #555; max-width: 60rem; }
+.cards { display: flex; flex-wrap: wrap; gap: 1rem; }
+.card { background: #f4f6f8; border-radius: 6px; padding: 1rem 1.5rem; min-width: 10rem; }
+.card .value { font-size: 1.6rem; font-weight: 600; }
+.card .label { color: #666; }
+table { border-collapse: collapse; margin-top: 1rem; font-size: .9rem; }
+th, td { padding: .35rem .7rem; border-bottom: 1px solid #eee; text-align: left; }
+th { cursor: pointer; background: #fafafa; user-select: none; }
+th.asc::after { content: ' \\25B2'; }
+th.desc::after { content: ' \\25BC'; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+.chart { max-width: 920px; font-size: 12px; }
+.chart rect { fill: #4a7fb5; }
+";
+
+const SORT_SCRIPT: &str = "
+document.querySelectorAll('table.sortable th').forEach(function (th) {
+  th.addEventListener('click', function () {
+    var column = th.cellIndex;
+    var table = th.closest('table');
+    var body = table.tBodies[0];
+    var ascending = !th.classList.contains('asc');
+    table.querySelectorAll('th').forEach(function (other) { other.classList.remove('asc', 'desc'); });
+    th.classList.add(ascending ? 'asc' : 'desc');
+    var key = function (row) {
+      var cell = row.cells[column];
+      if (cell === undefined) { return ''; }
+      return cell.dataset.sort !== undefined ? parseFloat(cell.dataset.sort) : cell.textContent;
+    };
+    Array.from(body.rows)
+      .sort(function (a, b) {
+        var x = key(a), y = key(b);
+        var order = typeof x === 'number' && typeof y === 'number' ? x - y : String(x).localeCompare(String(y));
+        return ascending ? order : -order;
+      })
+      .forEach(function (row) { body.appendChild(row); });
+  });
+});
+";
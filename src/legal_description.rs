@@ -0,0 +1,491 @@
+use std::str::FromStr;
+use duckdb::types::Value;
+use duckdb::{params, params_from_iter, Connection, Row};
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// The structured parts of a free-text legal description.
+#[derive(Debug, Default, PartialEq)]
+pub struct LegalDescription {
+    pub subdivision: Option<String>,
+    pub lot: Option<String>,
+    pub block: Option<String>,
+    pub section: Option<String>,
+    pub stated_acre: Option<Decimal>,
+    /// Marked "PT": only part of the lot or tract
+    pub is_part: bool,
+    /// Marked "RES": the residue left after earlier conveyances
+    pub is_residue: bool,
+}
+
+/// Words that end a subdivision name without being part of it.
+const SUBDIVISION_SUFFIXES: [&str; 5] = ["SUBDIVISION", "SUBD", "SUB", "S/D", "SD"];
+
+/// Words that only appear in descriptions of platted subdivisions.
+const SUBDIVISION_MARKERS: [&str; 8] = [
+    "SUBDIVISION", "SUBD", "S/D", "ADDITION", "ADDN", "ESTATES", "EST", "PHASE",
+];
+
+/// Parses a legal description such as `PT LOT 12 BLK 3 SEC 2 SUNSET HILLS SUBD` or
+/// `1.23 AC RES`.
+///
+/// Lot, block and section numbers follow their keyword (`LOT`/`LOTS`/`LT`, `BLK`/`BLOCK`,
+/// `SEC`/`SECTION`) and may be ranges or lists such as `4-6` or `4 & 5`. Acreage is a number
+/// followed by `AC`, `ACS`, `ACRE` or `ACRES`. The remaining words are taken as the
+/// subdivision name, but only when the description mentions a lot, block or an explicit
+/// subdivision marker; otherwise they are usually a road name or metes and bounds.
+pub fn parse_legal_description(text: &str) -> LegalDescription {
+    let upper = text.to_uppercase().replace([',', ';'], " ");
+    let tokens: Vec<&str> = upper.split_whitespace().collect();
+
+    let mut parsed = LegalDescription::default();
+    let mut name_words: Vec<&str> = Vec::new();
+    let mut has_marker = false;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i].trim_end_matches('.');
+        let keyword_target = match token {
+            "LOT" | "LOTS" | "LT" | "LTS" => Some(&mut parsed.lot),
+            "BLK" | "BLOCK" | "BL" => Some(&mut parsed.block),
+            "SEC" | "SECT" | "SECTION" => Some(&mut parsed.section),
+            _ => None,
+        };
+
+        if let Some(target) = keyword_target {
+            let (value, consumed) = take_identifier(&tokens[i + 1..]);
+            if value.is_some() && target.is_none() {
+                *target = value;
+            }
+            i += 1 + consumed;
+            continue;
+        }
+
+        match token {
+            "PT" | "PTS" | "PART" => parsed.is_part = true,
+            "RES" | "RESID" | "RESIDUE" => parsed.is_residue = true,
+            _ => {
+                if let Some((acres, consumed)) = take_acreage(&tokens[i..]) {
+                    parsed.stated_acre.get_or_insert(acres);
+                    i += consumed;
+                    continue;
+                }
+                if SUBDIVISION_MARKERS.contains(&token) {
+                    has_marker = true;
+                }
+                if token.chars().any(|c| c.is_ascii_alphabetic()) && !is_identifier(token) {
+                    name_words.push(token);
+                }
+            }
+        }
+        i += 1;
+    }
+
+    while name_words
+        .last()
+        .is_some_and(|word| SUBDIVISION_SUFFIXES.contains(word))
+    {
+        name_words.pop();
+    }
+    let platted = has_marker || parsed.lot.is_some() || parsed.block.is_some();
+    if platted && !name_words.is_empty() {
+        parsed.subdivision = Some(name_words.join(" "));
+    }
+
+    parsed
+}
+
+/// Whether a token is a lot, block or section identifier: a number with an optional
+/// letter suffix or range, or a single letter.
+fn is_identifier(token: &str) -> bool {
+    let starts_with_digit = token.chars().next().is_some_and(|c| c.is_ascii_digit());
+    let single_letter = token.len() == 1 && token.chars().all(|c| c.is_ascii_alphabetic());
+    (starts_with_digit && !token.contains('.')) || single_letter
+}
+
+/// Reads an identifier list such as `4`, `4-6` or `4 & 5` from the start of `tokens`,
+/// returning it and the number of tokens consumed.
+fn take_identifier(tokens: &[&str]) -> (Option<String>, usize) {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut consumed = 0;
+    for token in tokens {
+        let joiner = matches!(*token, "&" | "AND" | "-" | "THRU" | "TO");
+        if is_identifier(token) || (joiner && !parts.is_empty()) {
+            parts.push(token);
+            consumed += 1;
+        } else {
+            break;
+        }
+    }
+    // A dangling joiner belongs to whatever follows
+    while parts.last().is_some_and(|part| !is_identifier(part)) {
+        parts.pop();
+        consumed -= 1;
+    }
+    if parts.is_empty() {
+        (None, 0)
+    } else {
+        (Some(parts.join(" ")), consumed)
+    }
+}
+
+/// Reads an acreage such as `1.23 AC` or `1.23AC` from the start of `tokens`, returning it
+/// and the number of tokens consumed.
+fn take_acreage(tokens: &[&str]) -> Option<(Decimal, usize)> {
+    const UNITS: [&str; 4] = ["ACRES", "ACRE", "ACS", "AC"];
+    let first = tokens.first()?.trim_end_matches('.');
+
+    for unit in UNITS {
+        if let Some(acres) = first.strip_suffix(unit).and_then(parse_acres) {
+            return Some((acres, 1));
+        }
+    }
+    let acres = parse_acres(first)?;
+    let unit = tokens.get(1)?.trim_end_matches('.');
+    UNITS.contains(&unit).then_some((acres, 2))
+}
+
+/// Parses an acreage rounded to the six places `stated_acre` keeps, or `None` when it is
+/// too large for the column.
+fn parse_acres(number: &str) -> Option<Decimal> {
+    let acres = Decimal::from_str(number).ok()?.round_dp_with_strategy(6, RoundingStrategy::MidpointAwayFromZero);
+    (acres.abs() < Decimal::from(1_000_000_000_000i64)).then_some(acres)
+}
+
+/// Rebuilds the `parcel_legal_description` table by parsing each parcel's legal description.
+///
+/// `full_legal_description` is used when present, otherwise the three partial columns
+/// are joined together.
+pub fn build_legal_description_table(db: &Connection) -> Result<(), Error> {
+    db.execute("DELETE FROM parcel_legal_description", []).map_err(Error::append("parcel_legal_description"))?;
+
+    // Collect the descriptions first so reading and appending don't overlap
+    let mut stmt = db.prepare(
+        "SELECT DISTINCT
+            parcel_id,
+            tax_year,
+            COALESCE(
+                NULLIF(TRIM(full_legal_description), ''),
+                CONCAT_WS(' ', legal_description, legal_description_1, legal_description_2)
+            ) AS legal_text
+        FROM
+            parcel",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let parcel_id: Option<String> = row.get(0)?;
+        let tax_year: Option<i32> = row.get(1)?;
+        let legal_text: Option<String> = row.get(2)?;
+        if let Some(legal_text) = legal_text.filter(|text| !text.trim().is_empty()) {
+            records.push((parcel_id, tax_year, legal_text));
+        }
+    }
+
+    // The acreage goes as text for DuckDB to cast, since the appender can't take a DECIMAL
+    let mut appender = db.appender("parcel_legal_description").map_err(Error::append("parcel_legal_description"))?;
+    for (parcel_id, tax_year, legal_text) in &records {
+        let parsed = parse_legal_description(legal_text);
+        appender.append_row(params![
+            parcel_id,
+            tax_year,
+            parsed.subdivision,
+            parsed.lot,
+            parsed.block,
+            parsed.section,
+            parsed.stated_acre.map(|acres| acres.to_string()),
+            parsed.is_part,
+            parsed.is_residue,
+        ])
+        .map_err(Error::append("parcel_legal_description"))?;
+    }
+    appender.flush().map_err(Error::append("parcel_legal_description"))?;
+
+    Ok(())
+}
+
+/// Parcel, lot and value totals for one subdivision.
+struct SubdivisionRow {
+    subdivision: String,
+    parcel_count: i64,
+    lot_count: i64,
+    acres: Option<Decimal>,
+    total_appraised: Option<Decimal>,
+}
+
+impl FromRow for SubdivisionRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(SubdivisionRow {
+            subdivision: row.get("subdivision")?,
+            parcel_count: row.get("parcel_count")?,
+            lot_count: row.get("lot_count")?,
+            acres: get_decimal(row, "acres")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+        })
+    }
+}
+
+/// Returns the subdivisions named in legal descriptions, largest first.
+///
+/// A parcel is counted once however many of its cards describe it, and lots are told apart
+/// by block, so lot 1 of block A and lot 1 of block B are two lots.
+pub fn subdivisions(db: &Database, limit: usize, filter: &ParcelFilter) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        WITH described AS (
+            SELECT
+                l.subdivision,
+                l.parcel_id,
+                -- A lot number only identifies a lot within its block
+                COALESCE(l.block, '') || '/' || l.lot AS lot_key,
+                p.deeded_acre,
+                p.total_appraised
+            FROM
+                parcel_legal_description l
+                JOIN parcel_summary p
+                    ON p.parcel_id = l.parcel_id
+                    AND p.tax_year IS NOT DISTINCT FROM l.tax_year
+            WHERE
+                l.subdivision IS NOT NULL
+        ),
+        parcels AS (
+            -- Cards of one parcel may word its description differently; count it once
+            SELECT
+                subdivision,
+                parcel_id,
+                LIST(DISTINCT lot_key) AS lot_keys,
+                ANY_VALUE(deeded_acre) AS deeded_acre,
+                ANY_VALUE(total_appraised) AS total_appraised
+            FROM
+                described
+            GROUP BY
+                subdivision,
+                parcel_id
+        )
+        SELECT
+            subdivision,
+            COUNT(*) AS parcel_count,
+            LENGTH(LIST_DISTINCT(FLATTEN(LIST(lot_keys)))) AS lot_count,
+            SUM(deeded_acre) AS acres,
+            SUM(total_appraised) AS total_appraised
+        FROM
+            parcels
+        GROUP BY
+            subdivision
+        ORDER BY
+            parcel_count DESC
+        LIMIT
+            ?
+    ";
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.push(Value::BigInt(limit as i64));
+    let records: Vec<SubdivisionRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec!["Subdivision", "Parcels", "Distinct Lots", "Acres", "Total Appraised"];
+
+    // Callback to generate row data
+    let get_row_data = |record: &SubdivisionRow| -> Vec<Value> {
+        vec![
+            record.subdivision.clone().into(),
+            record.parcel_count.into(),
+            record.lot_count.into(),
+            nullable_decimal(record.acres),
+            nullable_decimal(record.total_appraised),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+/// A parcel whose legal description states a different acreage than it is deeded.
+struct AcreageMismatchRow {
+    parcel_id: Option<String>,
+    full_owner_name: Option<String>,
+    stated_acre: Option<Decimal>,
+    deeded_acre: Option<Decimal>,
+    is_part: bool,
+    is_residue: bool,
+}
+
+impl FromRow for AcreageMismatchRow {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(AcreageMismatchRow {
+            parcel_id: row.get("parcel_id")?,
+            full_owner_name: row.get("full_owner_name")?,
+            stated_acre: get_decimal(row, "stated_acre")?,
+            deeded_acre: get_decimal(row, "deeded_acre")?,
+            is_part: row.get("is_part")?,
+            is_residue: row.get("is_residue")?,
+        })
+    }
+}
+
+/// Returns parcels whose legal description states an acreage that differs from
+/// `deeded_acre` by more than `tolerance` (a fraction, so 0.1 is 10%).
+pub fn acreage_mismatches(
+    db: &Database,
+    tolerance: f64,
+    limit: usize,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
+
+    // Prepare the SQL query
+    let query = "
+        SELECT DISTINCT
+            l.parcel_id,
+            p.full_owner_name,
+            l.stated_acre,
+            p.deeded_acre,
+            l.is_part,
+            l.is_residue
+        FROM
+            parcel_legal_description l
+            JOIN parcel_summary p
+                ON p.parcel_id = l.parcel_id
+                AND p.tax_year IS NOT DISTINCT FROM l.tax_year
+        WHERE
+            l.stated_acre > 0
+            AND p.deeded_acre IS NOT NULL
+            AND ABS(l.stated_acre - p.deeded_acre) > ? * l.stated_acre
+        ORDER BY
+            ABS(l.stated_acre - p.deeded_acre) DESC
+        LIMIT
+            ?
+    ";
+
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.extend([Value::Double(tolerance), Value::BigInt(limit as i64)]);
+    let records: Vec<AcreageMismatchRow> = query_as(db, &query, params_from_iter(values))?;
+
+    // Define headers for printing
+    let headers = vec![
+        "Parcel ID",
+        "Owner",
+        "Stated Acre",
+        "Deeded Acre",
+        "Difference",
+        "Part/Residue",
+    ];
+
+    // Callback to generate row data
+    let get_row_data = |record: &AcreageMismatchRow| -> Vec<Value> {
+        let marker = match (record.is_part, record.is_residue) {
+            (true, true) => "PT RES",
+            (true, false) => "PT",
+            (false, true) => "RES",
+            (false, false) => "",
+        };
+        vec![
+            nullable(record.parcel_id.clone()),
+            nullable(record.full_owner_name.clone()),
+            nullable_decimal(record.stated_acre),
+            nullable_decimal(record.deeded_acre),
+            nullable_decimal(record.deeded_acre.zip(record.stated_acre).map(|(deeded, stated)| deeded - stated)),
+            marker.to_string().into(),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lot_block_section_and_subdivision() {
+        let parsed = parse_legal_description("PT LOT 12 BLK 3 SEC 2 SUNSET HILLS SUBD");
+        assert_eq!(
+            parsed,
+            LegalDescription {
+                subdivision: Some("SUNSET HILLS".to_string()),
+                lot: Some("12".to_string()),
+                block: Some("3".to_string()),
+                section: Some("2".to_string()),
+                stated_acre: None,
+                is_part: true,
+                is_residue: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_lot_ranges_and_lists() {
+        assert_eq!(parse_legal_description("LOTS 4-6 MEADOW ESTATES").lot.as_deref(), Some("4-6"));
+        assert_eq!(parse_legal_description("LOTS 4 & 5 BLK A OAK RIDGE").lot.as_deref(), Some("4 & 5"));
+        assert_eq!(parse_legal_description("LOTS 4 & 5 BLK A OAK RIDGE").block.as_deref(), Some("A"));
+        // A dangling joiner isn't part of the lot
+        assert_eq!(parse_legal_description("LOT 7 & RIVER VIEW S/D").lot.as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn parses_acreage_with_and_without_a_space() {
+        let parsed = parse_legal_description("1.23 AC RES");
+        assert_eq!(parsed.stated_acre, Some(Decimal::new(123, 2)));
+        assert!(parsed.is_residue);
+        assert_eq!(parse_legal_description("12.5ACRES HILLTOP").stated_acre, Some(Decimal::new(125, 1)));
+        assert_eq!(parse_legal_description("LOT 3 0.75 AC. GREEN ACRES").stated_acre, Some(Decimal::new(75, 2)));
+        // A number too large for the column is no acreage
+        assert_eq!(parse_legal_description("1234567890123 AC").stated_acre, None);
+    }
+
+    #[test]
+    fn only_names_a_subdivision_when_the_land_is_platted() {
+        // Road names and metes and bounds aren't subdivisions
+        assert_eq!(parse_legal_description("5.00 AC OFF BACK CREEK RD").subdivision, None);
+        assert_eq!(parse_legal_description("HARPERS ADDITION").subdivision.as_deref(), Some("HARPERS ADDITION"));
+        assert_eq!(parse_legal_description("LOT 9 CEDAR KNOLLS SUB").subdivision.as_deref(), Some("CEDAR KNOLLS"));
+        assert_eq!(parse_legal_description(""), LegalDescription::default());
+    }
+
+    #[test]
+    fn counts_parcels_once_and_lots_per_block() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_legal_description, deeded_acre, land_appraised, total_appraised)
+            VALUES
+                (1, '02-01-0001', 2024, 1, 'LOT 1 BLK A OAK RIDGE SUBD', 0.5, 1000, 1000),
+                (2, '02-01-0001', 2024, 2, 'LOT 1 BLK A OAK RIDGE SUBDIVISION', 0.5, 1000, 1000),
+                (3, '02-01-0002', 2024, 1, 'LOT 1 BLK B OAK RIDGE SUBD', 0.25, 2000, 2000);",
+        )
+        .unwrap();
+        build_legal_description_table(&db).unwrap();
+
+        let result = subdivisions(&db, 10, &ParcelFilter::default()).unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0][0], Value::Text("OAK RIDGE".to_string()));
+        assert_eq!(result.rows[0][1], Value::BigInt(2));
+        assert_eq!(result.rows[0][2], Value::BigInt(2));
+        assert_eq!(result.rows[0][3], nullable_decimal(Some(Decimal::new(75, 2))));
+        assert_eq!(result.rows[0][4], nullable_decimal(Some(Decimal::new(3000, 0))));
+    }
+
+    #[test]
+    fn compares_stated_and_deeded_acreage_exactly() {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_legal_description, deeded_acre)
+            VALUES
+                (1, '02-01-0001', 2024, 1, '1.5 AC RES', 1.2),
+                (2, '02-01-0002', 2024, 1, '0.1 AC', 0.1);",
+        )
+        .unwrap();
+        build_legal_description_table(&db).unwrap();
+
+        let result = acreage_mismatches(&db, 0.1, 10, &ParcelFilter::default()).unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0][0], Value::Text("02-01-0001".to_string()));
+        assert_eq!(result.rows[0][2], nullable_decimal(Some(Decimal::new(15, 1))));
+        assert_eq!(result.rows[0][4], nullable_decimal(Some(Decimal::new(-3, 1))));
+        assert_eq!(result.rows[0][5], Value::Text("RES".to_string()));
+    }
+}
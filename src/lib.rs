@@ -0,0 +1,53 @@
+//! Processing parcel data with Rust and DuckDB.
+//!
+//! The library reads county parcel DBF files into [`ParcelRecord`]s, loads them into a
+//! DuckDB [`Database`] and runs reports over it. Reports come back as [`ResultSet`]s, which
+//! [`Output`] writes as tables, CSV, JSON or Parquet.
+//!
+//! ```no_run
+//! use rust_real_e::{db, Database, Output, ParcelFilter, ReportRegistry};
+//!
+//! # fn main() -> rust_real_e::Result<()> {
+//! let database = Database::open_file("parcel_data.db")?;
+//! db::load_dbf("ParcelSummary_2024_Berkeley.dbf", &database)?;
+//!
+//! let registry = ReportRegistry::load(None)?;
+//! let result = registry
+//!     .get("owners-by-value")?
+//!     .run(&database, &Default::default(), &ParcelFilter::default())?;
+//! Output::default().write(&result)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod parcel_record;
+pub mod db;
+pub mod database;
+pub mod migrations;
+mod data_mapper;
+pub mod pretty_print;
+pub mod dbf;
+pub mod query;
+pub mod comps;
+pub mod scoring;
+pub mod flood;
+pub mod deed;
+pub mod legal_description;
+pub mod buildout;
+pub mod output;
+pub mod html;
+pub mod shell;
+pub mod report;
+pub mod filter;
+pub mod row;
+pub mod server;
+pub mod geometry;
+pub mod tiles;
+pub mod error;
+
+pub use crate::database::{Database, DatabaseOptions};
+pub use crate::error::{Error, Result};
+pub use crate::filter::ParcelFilter;
+pub use crate::output::{Output, OutputFormat, ResultSet};
+pub use crate::parcel_record::ParcelRecord;
+pub use crate::report::{ReportRegistry, SavedReport};
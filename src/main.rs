@@ -1,42 +1,399 @@
-mod parcel_record;
-mod db;
-mod data_mapper;
-mod pretty_print;
-mod dbf;
-mod query;
+use rust_real_e::comps::CompWeights;
+use rust_real_e::scoring::ScoringConfig;
+use rust_real_e::flood::FloodBreakdown;
+use rust_real_e::buildout::BuildoutGrouping;
+use rust_real_e::output::{Output, OutputFormat, ResultSet};
+use rust_real_e::report::{parse_args, ReportRegistry};
+use rust_real_e::filter::ParcelFilter;
+use rust_real_e::html::{Chart, HtmlReport};
+use rust_real_e::geometry::SourceCrs;
+use rust_real_e::{buildout, comps, db, deed, flood, geometry, legal_description, query, scoring, server, shell, tiles};
+use clap::{Parser, Subcommand};
+use rust_real_e::{Database, DatabaseOptions, Error, Result};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use rust_real_e::pretty_print::{print_parcel_table_schema, TableOptions, TableStyle};
 
-use crate::parcel_record::ParcelRecord;
-use crate::dbf::read;
-use duckdb::{Result};
-use std::error::Error;
-use crate::pretty_print::print_parcel_table_schema;
-use crate::query::find_potential_redemption_properties;
+const DBF_FILE: &str = "test_data/Berkeley_02_WVGISTCTax_2024_UTM83/ParcelSummary_2024_Berkeley.dbf";
+const REPORTS_DIR: &str = "reports";
+// Rejected records printed after a load; the rest are only in ingest_reject
+const MAX_REJECTS_SHOWN: usize = 10;
 
-const dbfFile: &str = "test_data/Berkeley_02_WVGISTCTax_2024_UTM83/ParcelSummary_2024_Berkeley.dbf";
-const duckdbFile: &str = "parcel_data.db";
+/// Processing parcel data with Rust and DuckDB.
+///
+/// Without a subcommand the DBF file is loaded into DuckDB and the standard analyses are printed.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Format reports are written in
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+    /// Write the report to this file instead of standard output
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+    /// How tables are drawn
+    #[arg(long, global = true, value_enum, default_value_t = TableStyle::Plain)]
+    style: TableStyle,
+    /// Cut table cells longer than this short with an ellipsis
+    #[arg(long, global = true, default_value_t = 60)]
+    max_column_width: usize,
+    /// Directory of saved report SQL files; defaults to ./reports when it exists
+    #[arg(long, global = true)]
+    reports_dir: Option<PathBuf>,
+    #[command(flatten)]
+    filter: ParcelFilter,
+    #[command(flatten)]
+    database: DatabaseOptions,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a saved report, or render the standard analyses into an HTML report
+    Report {
+        /// Saved report to run; see --list
+        #[arg(required_unless_present_any = ["html", "list"])]
+        name: Option<String>,
+        /// Report parameter as KEY=VALUE; may be repeated
+        #[arg(short, long = "param", value_name = "KEY=VALUE")]
+        params: Vec<String>,
+        /// List the saved reports and their parameters
+        #[arg(long, conflicts_with_all = ["name", "html"])]
+        list: bool,
+        /// Describe the report's parameters instead of running it
+        #[arg(long, requires = "name")]
+        describe: bool,
+        /// Write the standard analyses to this self-contained HTML file instead
+        #[arg(long, conflicts_with = "name")]
+        html: Option<PathBuf>,
+        /// Maximum number of rows to show; short for --param limit=N
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// List land uses, or the parcels with one land use
+    LandUse {
+        /// Land use to list parcels for, e.g. RESIDENTIAL; lists every land use when omitted
+        name: Option<String>,
+        /// Maximum number of parcels to show
+        #[arg(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Open an interactive SQL shell on the parcel database
+    Shell,
+    /// Serve a parcel map viewer, with parcel lookups, owner search, the saved reports and
+    /// parcel vector tiles as a local JSON API
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// Number of request threads, each with its own read-only connection
+        #[arg(long, default_value_t = 4)]
+        threads: usize,
+        /// parcel_summary columns to carry on tile features, comma-separated
+        #[arg(long, value_delimiter = ',', default_values = tiles::DEFAULT_ATTRIBUTES)]
+        tile_attributes: Vec<String>,
+        /// TOML file of distress rules for the distress_score attribute; the built-in rules
+        /// are used when omitted
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+    },
+    /// Load parcel outlines from a shapefile for the map tiles
+    Geometry {
+        /// Polygon shapefile (.shp) of parcel outlines, with its .dbf alongside
+        shapefile: PathBuf,
+        /// .dbf field holding the parcel ID, formatted or clean
+        #[arg(long, default_value = "CleanParcel")]
+        id_field: String,
+        /// UTM zone of the shapefile's coordinates; longitude and latitude when omitted
+        #[arg(long)]
+        utm_zone: Option<u8>,
+    },
+    /// Find the parcels most similar to a given parcel
+    Comps {
+        /// Parcel ID of the subject parcel
+        parcel_id: String,
+        /// Number of comparable parcels to return
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+    },
+    /// Score parcels against weighted distress rules
+    Score {
+        /// TOML file of distress rules; the built-in rules are used when omitted
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+        /// Only show parcels scoring at least this much
+        #[arg(long, default_value_t = 3.0)]
+        min_score: f64,
+        /// Maximum number of parcels to show
+        #[arg(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Report parcel value and acreage exposed to flood risk
+    Flood {
+        /// How to break down flood exposure
+        #[arg(long, value_enum, default_value_t = FloodBreakdown::Zone)]
+        by: FloodBreakdown,
+        /// Show the owners with the most appraised value in flood hazard areas instead
+        #[arg(long)]
+        owners: bool,
+        /// Maximum number of owners to show
+        #[arg(short = 'n', long, default_value_t = 25)]
+        limit: usize,
+    },
+    /// Report on deed book/page references
+    Deeds {
+        #[command(subcommand)]
+        report: DeedReport,
+    },
+    /// Report on subdivisions and acreage parsed from legal descriptions
+    Legal {
+        #[command(subcommand)]
+        report: LegalReport,
+    },
+    /// Track build-out of lots per subdivision or developer
+    Buildout {
+        /// What to group lots by
+        #[arg(long, value_enum, default_value_t = BuildoutGrouping::Subdivision)]
+        by: BuildoutGrouping,
+        /// Only include groups matching this LIKE pattern, e.g. '%LGI%'
+        #[arg(long)]
+        name: Option<String>,
+        /// Maximum number of groups to show
+        #[arg(short = 'n', long, default_value_t = 25)]
+        limit: usize,
+    },
+}
+
+/// Runs the saved report `name` with `KEY=VALUE` parameters and an optional row limit over
+/// the parcels matching `filter`.
+fn run_report(
+    db: &Database,
+    registry: &ReportRegistry,
+    name: &str,
+    params: &[String],
+    limit: Option<usize>,
+    filter: &ParcelFilter,
+) -> Result<ResultSet> {
+    let report = registry.get(name)?;
+    let mut args = parse_args(params)?;
+    if let Some(limit) = limit {
+        args.insert("limit".to_string(), limit.to_string());
+    }
+    report.run(db, &args, filter)
+}
+
+/// Renders the standard analyses into one HTML file for sharing outside the terminal.
+fn write_html_report(
+    db: &Database,
+    registry: &ReportRegistry,
+    path: &Path,
+    limit: Option<usize>,
+    filter: &ParcelFilter,
+) -> Result<()> {
+    let mut report = HtmlReport::new("Parcel Data Report");
+    report.add_cards(&run_report(db, registry, "summary", &[], None, filter)?);
+
+    // Bar charts plot the owner or land use against the column the section is ranked by
+    let sections = [
+        ("owners-by-parcels", Some(1)),
+        ("owners-by-value", Some(1)),
+        ("valuation", Some(6)),
+        ("redemption", None),
+        ("outliers", None),
+    ];
+    for (name, value_column) in sections {
+        let meta = &registry.get(name)?.meta;
+        report.add_section(
+            &meta.title,
+            meta.description.as_deref(),
+            run_report(db, registry, name, &[], limit, filter)?,
+            value_column.map(|value_column| Chart { label_column: 0, value_column }),
+        );
+    }
+
+    report.write(path)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+#[derive(Subcommand)]
+enum DeedReport {
+    /// Deeds that convey more than one parcel
+    Shared {
+        /// Maximum number of deeds to show
+        #[arg(short = 'n', long, default_value_t = 25)]
+        limit: usize,
+    },
+    /// Parcels with a pending transfer to a new owner
+    Pending {
+        /// Maximum number of parcels to show
+        #[arg(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// The chain of deeds recorded for a parcel across tax years
+    Chain {
+        /// Parcel ID to trace
+        parcel_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LegalReport {
+    /// Subdivisions named in legal descriptions, largest first
+    Subdivisions {
+        /// Maximum number of subdivisions to show
+        #[arg(short = 'n', long, default_value_t = 25)]
+        limit: usize,
+    },
+    /// Parcels whose stated acreage disagrees with the deeded acreage
+    Acreage {
+        /// Allowed difference as a fraction of the stated acreage
+        #[arg(long, default_value_t = 0.1)]
+        tolerance: f64,
+        /// Maximum number of parcels to show
+        #[arg(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
 
-fn main() -> Result<(), Box<dyn Error>> {
+    let Some(command) = cli.command else {
+        if cli.format != OutputFormat::Table || cli.output.is_some() || !cli.filter.is_empty() {
+            return Err(Error::InvalidInput(
+                "--format, --output and filters need a subcommand, e.g. `report owners-by-value`".to_string(),
+            ));
+        }
+        return ingest_and_analyse(&Database::open(&cli.database)?, &load_reports(cli.reports_dir.as_deref())?);
+    };
+    if matches!(command, Command::Shell) && cli.output.is_some() {
+        return Err(Error::InvalidInput(
+            "--output can't be used with `shell`, which writes its results to the terminal".to_string(),
+        ));
+    }
+    let registry = load_reports(cli.reports_dir.as_deref())?;
 
+    // The server never writes, so it leaves the database free for other processes
+    let options = match command {
+        Command::Serve { .. } => cli.database.read_only(),
+        _ => cli.database,
+    };
+    let db = Database::open(&options)?;
+
+    let filter = &cli.filter;
+    let table = TableOptions::for_terminal(cli.style, Some(cli.max_column_width));
+    let result = match command {
+        Command::Report { name, params, list, describe, html, limit } => match (name, html) {
+            _ if list => registry.list(),
+            (Some(name), None) if describe => registry.get(&name)?.describe(),
+            (_, Some(path)) => return write_html_report(&db, &registry, &path, limit, filter),
+            (Some(name), None) => run_report(&db, &registry, &name, &params, limit, filter)?,
+            (None, None) => unreachable!("clap requires a report name, --list or --html"),
+        },
+        Command::LandUse { name, limit } => match name {
+            Some(name) => query::get_parcels_by_land_use(&db, &name, limit, filter)?,
+            None => query::get_land_uses(&db, filter)?,
+        },
+        Command::Shell => {
+            let output = Output::new(cli.format, None, table);
+            return shell::run_shell(&db, &registry, filter, output);
+        }
+        Command::Serve { addr, threads, tile_attributes, config } => {
+            let scoring = ScoringConfig::load(config.as_deref())?;
+            return server::serve(&db, &addr, threads, &registry, &tile_attributes, &scoring);
+        }
+        Command::Geometry { shapefile, id_field, utm_zone } => {
+            let crs = match utm_zone {
+                Some(zone) => SourceCrs::Utm { zone },
+                None => SourceCrs::LonLat,
+            };
+            let (loaded, matched) = geometry::load_parcel_geometry(&db, &shapefile, &id_field, crs)?;
+            println!("Loaded {} parcel outlines, {} matched to parcels", loaded, matched);
+            return Ok(());
+        }
+        Command::Comps { parcel_id, count } => {
+            comps::find_comparable_parcels(&db, &parcel_id, count, &CompWeights::default(), filter)?
+        }
+        Command::Score { config, min_score, limit } => {
+            let config = ScoringConfig::load(config.as_deref())?;
+            scoring::score_distressed_properties(&db, &config, min_score, limit, filter)?
+        }
+        Command::Flood { by, owners, limit } => {
+            if owners {
+                flood::owner_flood_exposure(&db, limit, filter)?
+            } else {
+                flood::flood_exposure(&db, by, filter)?
+            }
+        }
+        Command::Deeds { report } => {
+            match report {
+                DeedReport::Shared { limit } => deed::shared_deeds(&db, limit, filter)?,
+                DeedReport::Pending { limit } => deed::pending_transfers(&db, limit, filter)?,
+                DeedReport::Chain { parcel_id } => deed::deed_chain(&db, &parcel_id, filter)?,
+            }
+        }
+        Command::Legal { report } => {
+            match report {
+                LegalReport::Subdivisions { limit } => legal_description::subdivisions(&db, limit, filter)?,
+                LegalReport::Acreage { tolerance, limit } => {
+                    legal_description::acreage_mismatches(&db, tolerance, limit, filter)?
+                }
+            }
+        }
+        Command::Buildout { by, name, limit } => {
+            buildout::buildout(&db, by, name.as_deref(), limit, filter)?
+        }
+    };
+
+    Output::new(cli.format, cli.output, table).write(&result)
+}
+
+/// Loads the built-in reports plus those in `dir`, or in ./reports when no directory is given.
+fn load_reports(dir: Option<&Path>) -> Result<ReportRegistry> {
+    let default = Path::new(REPORTS_DIR);
+    let dir = dir.or_else(|| default.is_dir().then_some(default));
+    ReportRegistry::load(dir)
+}
 
-    // Read the parcel data
-    let mut records: Vec<ParcelRecord> = read(String::from(dbfFile))?;
+fn ingest_and_analyse(db: &Database, registry: &ReportRegistry) -> Result<()> {
+    // Read the parcel data and save it to DuckDB
+    let run = db::load_dbf(DBF_FILE, db)?;
+    println!("Saved {} records to DuckDB", run.rows_loaded);
+    if !run.rejected.is_empty() {
+        eprintln!("Rejected {} records, listed in ingest_reject for run {}:", run.rejected.len(), run.run_id);
+        for error in run.rejected.iter().take(MAX_REJECTS_SHOWN) {
+            eprintln!("  {}", error);
+        }
+    }
+    print_parcel_table_schema(db)?;
 
-    // Save to DuckDB
-    db::save_to_duckdb(duckdbFile, &mut records).expect("Fatal error saving to duckdb");
-    print_parcel_table_schema().expect("no duckdb file found");
-    // // Print first five records as a test to make sure things are working
-    // query::print_first_five_records()?;
+    let output = Output::default();
 
     println!("\n\n\nGetting names with most parcels\n");
-    query::get_names_with_most_parcels(7)?;
+    output.write(&run_report(db, registry, "owners-by-parcels", &[], None, &ParcelFilter::default())?)?;
 
     println!("\n\n\nGet the top owners by total appraised value\n");
-    query::get_total_appraised_value_per_owner(7)?;
+    output.write(&run_report(db, registry, "owners-by-value", &[], None, &ParcelFilter::default())?)?;
 
     println!("\n\n\nfind_potential_redemption_properties\n");
-    find_potential_redemption_properties()?;
+    output.write(&run_report(db, registry, "redemption", &[], None, &ParcelFilter::default())?)?;
 
+    println!("\n\n\nFind appraisals that stand out from comparable parcels\n");
+    output.write(&run_report(db, registry, "outliers", &[], None, &ParcelFilter::default())?)?;
 
+    println!("\n\n\nFlood exposure by zone\n");
+    output.write(&flood::flood_exposure(db, FloodBreakdown::Zone, &ParcelFilter::default())?)?;
 
     Ok(())
 }
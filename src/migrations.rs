@@ -0,0 +1,177 @@
+use duckdb::Connection;
+use crate::db::{create_parcel_views, rebuild_derived_tables};
+use crate::error::Error;
+
+/// Schema migrations in the order they are applied, as (description, SQL file) pairs. A
+/// migration's version is its position in the list, counting from 1.
+///
+/// Applied migrations must never be edited; change the schema by adding one at the end.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("parcel table", include_str!("../migrations/001_parcel.sql")),
+    ("ingest run provenance", include_str!("../migrations/002_ingest_run.sql")),
+    ("typed parcel columns", include_str!("../migrations/003_typed_columns.sql")),
+    ("derived tables", include_str!("../migrations/004_derived_tables.sql")),
+    ("parcel outline centroids", include_str!("../migrations/005_geometry_centroids.sql")),
+    ("ingest run status and rejected records", include_str!("../migrations/006_ingest_rejects.sql")),
+    ("decimal stated acreage", include_str!("../migrations/007_decimal_stated_acre.sql")),
+];
+
+// The migration that created the tables derived from parcel, which are parsed in Rust and
+// so can't be filled by the SQL
+const DERIVED_TABLES_VERSION: i64 = 4;
+
+/// The schema version this build creates and expects.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Returns the schema version recorded in the database, 0 when none has been.
+pub fn schema_version(conn: &Connection) -> duckdb::Result<i64> {
+    let recorded: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM duckdb_tables() WHERE table_name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !recorded {
+        return Ok(0);
+    }
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+/// Brings the database `name` up to [`SCHEMA_VERSION`], each migration in its own
+/// transaction, then recreates the parcel views over the migrated tables and fills the
+/// derived tables when they were created.
+///
+/// Fails without changing anything when the database is newer than this build, or when it
+/// is read-only and behind.
+pub(crate) fn migrate(conn: &mut Connection, name: &str, read_only: bool) -> Result<(), Error> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew { path: name.to_string(), version, supported: SCHEMA_VERSION });
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    if read_only {
+        return Err(Error::SchemaOutdated { path: name.to_string(), version, current: SCHEMA_VERSION });
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            description TEXT,
+            applied_at TIMESTAMP DEFAULT current_timestamp
+        )",
+        [],
+    )
+    .map_err(Error::schema("schema_version"))?;
+
+    for (index, (description, sql)) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let version = index as i64 + 1;
+        let migration_error = |source| Error::Migration {
+            version,
+            description: description.to_string(),
+            source: Box::new(source),
+        };
+        let tx = conn.transaction().map_err(migration_error)?;
+        tx.execute_batch(sql).map_err(migration_error)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?, ?)",
+            duckdb::params![version, description],
+        )
+        .map_err(migration_error)?;
+        tx.commit().map_err(migration_error)?;
+    }
+
+    // Views aren't versioned; they follow whatever columns the tables now have
+    create_parcel_views(conn).map_err(Error::schema("the parcel views"))?;
+    if version < DERIVED_TABLES_VERSION {
+        rebuild_derived_tables(conn)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An in-memory database as builds made it before schema versions were recorded: the
+    // first parcel table and nothing else, with one parcel in a flood zone
+    fn unversioned_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].1).unwrap();
+        conn.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, flood_risks, total_appraised)
+            VALUES (1, 'A', 2024, 1, 'ZONE AE', 1234.5);",
+        )
+        .unwrap();
+        conn
+    }
+
+    // The type of `column` in `table`
+    fn column_type(conn: &Connection, table: &str, column: &str) -> String {
+        conn.query_row(
+            "SELECT data_type FROM duckdb_columns() WHERE table_name = ? AND column_name = ?",
+            [table, column],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn upgrades_an_unversioned_database() {
+        let mut conn = unversioned_database();
+        migrate(&mut conn, "old.db", false).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        let recorded: i64 = conn.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(recorded, SCHEMA_VERSION);
+        assert_eq!(column_type(&conn, "parcel", "tax_year"), "INTEGER");
+        assert_eq!(column_type(&conn, "parcel", "total_appraised"), "DECIMAL(14,2)");
+
+        // The parcel survives the typed rebuild, and the derived tables are filled from it
+        let (tax_year, total_appraised): (i32, String) = conn
+            .query_row("SELECT tax_year, total_appraised::TEXT FROM parcel WHERE parcel_id = 'A'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((tax_year, total_appraised.as_str()), (2024, "1234.50"));
+        let zone: String = conn
+            .query_row("SELECT zone FROM parcel_flood_zone WHERE parcel_id = 'A'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(zone, "AE");
+
+        // A current database is left as it is
+        migrate(&mut conn, "old.db", false).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn refuses_a_database_from_a_newer_build() {
+        let mut conn = unversioned_database();
+        migrate(&mut conn, "new.db", false).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?, 'from the future')",
+            [SCHEMA_VERSION + 1],
+        )
+        .unwrap();
+
+        let result = migrate(&mut conn, "new.db", false);
+        assert!(matches!(
+            result,
+            Err(Error::SchemaTooNew { path, version, supported })
+                if path == "new.db" && version == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn refuses_to_migrate_a_read_only_database() {
+        let mut conn = unversioned_database();
+
+        let result = migrate(&mut conn, "old.db", true);
+        assert!(matches!(
+            result,
+            Err(Error::SchemaOutdated { path, version: 0, current }) if path == "old.db" && current == SCHEMA_VERSION
+        ));
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(column_type(&conn, "parcel", "tax_year"), "DOUBLE");
+    }
+}
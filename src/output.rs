@@ -0,0 +1,338 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use chrono::DateTime;
+use clap::ValueEnum;
+use duckdb::types::{TimeUnit, Value};
+use duckdb::{appender_params_from_iter, Connection, Params};
+use rust_decimal::Decimal;
+use serde_json::{Map, Number, Value as JsonValue};
+use crate::pretty_print::{render_result, ColumnFormat, TableOptions};
+use crate::error::Error;
+
+/// The rows of a report, ready to be rendered or exported.
+#[derive(Debug, Clone)]
+pub struct ResultSet {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Display format per column; a missing entry is guessed from the header
+    pub formats: Vec<Option<ColumnFormat>>,
+}
+
+impl ResultSet {
+    /// Builds a result set from report records, converting each one into a row of values.
+    pub fn new<T>(headers: Vec<&str>, records: &[T], get_row_data: impl Fn(&T) -> Vec<Value>) -> Self {
+        ResultSet {
+            headers: headers.into_iter().map(String::from).collect(),
+            rows: records.iter().map(get_row_data).collect(),
+            formats: Vec::new(),
+        }
+    }
+
+    /// The display format of column `i`.
+    pub fn column_format(&self, i: usize) -> ColumnFormat {
+        self.formats
+            .get(i)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| ColumnFormat::from_header(&self.headers[i]))
+    }
+
+    /// Runs an arbitrary query and collects every column, named as the query names it.
+    pub fn query(conn: &Connection, sql: &str, params: impl Params) -> duckdb::Result<Self> {
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query(params)?;
+
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            let column_count = row.as_ref().column_count();
+            let values = (0..column_count)
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<duckdb::Result<Vec<Value>>>()?;
+            records.push(values);
+        }
+        drop(rows);
+
+        Ok(ResultSet {
+            headers: stmt.column_names(),
+            rows: records,
+            formats: Vec::new(),
+        })
+    }
+}
+
+/// Converts an optional column value into a `Value`, mapping `None` to `NULL`.
+pub fn nullable<T: Into<Value>>(value: Option<T>) -> Value {
+    value.map_or(Value::Null, Into::into)
+}
+
+/// Like [`nullable`], for decimals, which `Value` has no `From` conversion for.
+pub fn nullable_decimal(value: Option<Decimal>) -> Value {
+    value.map_or(Value::Null, Value::Decimal)
+}
+
+/// The formats reports can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned text table
+    #[default]
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+    /// A JSON array of objects
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Apache Parquet, written through DuckDB; needs an output file
+    Parquet,
+}
+
+/// Where and how reports are written.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub format: OutputFormat,
+    /// File to write to; standard output when `None`
+    pub path: Option<PathBuf>,
+    /// Layout of the `table` format
+    pub table: TableOptions,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, path: Option<PathBuf>, table: TableOptions) -> Self {
+        Output { format, path, table }
+    }
+
+    /// Writes a report in the configured format.
+    pub fn write(&self, result: &ResultSet) -> Result<(), Error> {
+        match self.format {
+            OutputFormat::Table => {
+                // Only a table going to the terminal is squeezed to the terminal's width
+                let mut options = self.table.clone();
+                if self.path.is_some() {
+                    options.max_width = None;
+                }
+                self.write_with(|out| {
+                    out.write_all(render_result(result, &options).as_bytes())?;
+                    Ok(())
+                })
+            }
+            OutputFormat::Csv => self.write_with(|out| write_csv(out, result)),
+            OutputFormat::Json => self.write_with(|out| {
+                serde_json::to_writer_pretty(&mut *out, &to_json_rows(result))?;
+                writeln!(out)?;
+                Ok(())
+            }),
+            OutputFormat::Ndjson => self.write_with(|out| {
+                for row in to_json_rows(result) {
+                    serde_json::to_writer(&mut *out, &row)?;
+                    writeln!(out)?;
+                }
+                Ok(())
+            }),
+            OutputFormat::Parquet => match &self.path {
+                Some(path) => write_parquet(&path.to_string_lossy(), result),
+                None => Err(Error::InvalidInput("parquet output needs a file; pass --output".to_string())),
+            },
+        }
+    }
+
+    // Runs a writer against the output file, or standard output when there is none
+    fn write_with(
+        &self,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut out: Box<dyn Write> = match &self.path {
+            Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|e| Error::file(path, e))?)),
+            None => Box::new(io::stdout().lock()),
+        };
+        write(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Formats a value for display in a text table.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Boolean(b) => if *b { "yes" } else { "no" }.to_string(),
+        Value::Float(v) => format!("{:.2}", v),
+        Value::Double(v) => format!("{:.2}", v),
+        Value::Decimal(d) => d.to_string(),
+        Value::Text(s) | Value::Enum(s) => s.clone(),
+        other => match to_json(other) {
+            JsonValue::String(s) => s,
+            json => json.to_string(),
+        },
+    }
+}
+
+fn write_csv(out: &mut dyn Write, result: &ResultSet) -> Result<(), Error> {
+    let escape = |field: &str| -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+    let header: Vec<String> = result.headers.iter().map(|h| escape(h)).collect();
+    writeln!(out, "{}", header.join(","))?;
+    for row in &result.rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|value| match to_json(value) {
+                // Decimals keep their exact digits rather than going through a double
+                _ if matches!(value, Value::Decimal(_)) => format_value(value),
+                JsonValue::Null => String::new(),
+                JsonValue::String(s) => escape(&s),
+                json => escape(&json.to_string()),
+            })
+            .collect();
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Converts each row to a JSON object keyed by column name.
+pub fn to_json_rows(result: &ResultSet) -> Vec<JsonValue> {
+    result
+        .rows
+        .iter()
+        .map(|row| {
+            let object: Map<String, JsonValue> = result
+                .headers
+                .iter()
+                .zip(row)
+                .map(|(header, value)| (header.clone(), to_json(value)))
+                .collect();
+            JsonValue::Object(object)
+        })
+        .collect()
+}
+
+/// Converts a DuckDB value into JSON. Dates and timestamps become ISO 8601 strings.
+pub fn to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Boolean(b) => JsonValue::Bool(*b),
+        Value::TinyInt(v) => (*v).into(),
+        Value::SmallInt(v) => (*v).into(),
+        Value::Int(v) => (*v).into(),
+        Value::BigInt(v) => (*v).into(),
+        Value::HugeInt(v) => i64::try_from(*v).map_or_else(|_| JsonValue::String(v.to_string()), Into::into),
+        Value::UTinyInt(v) => (*v).into(),
+        Value::USmallInt(v) => (*v).into(),
+        Value::UInt(v) => (*v).into(),
+        Value::UBigInt(v) => (*v).into(),
+        Value::Float(v) => Number::from_f64(*v as f64).map_or(JsonValue::Null, JsonValue::Number),
+        Value::Double(v) => Number::from_f64(*v).map_or(JsonValue::Null, JsonValue::Number),
+        Value::Decimal(d) => d
+            .to_string()
+            .parse::<Number>()
+            .map_or_else(|_| JsonValue::String(d.to_string()), JsonValue::Number),
+        Value::Text(s) | Value::Enum(s) => JsonValue::String(s.clone()),
+        Value::Date32(days) => match DateTime::from_timestamp(i64::from(*days) * 86_400, 0) {
+            Some(date) => JsonValue::String(date.date_naive().to_string()),
+            None => JsonValue::String(format!("{:?}", value)),
+        },
+        Value::Timestamp(unit, ticks) => {
+            let micros = match unit {
+                TimeUnit::Second => ticks.checked_mul(1_000_000),
+                TimeUnit::Millisecond => ticks.checked_mul(1_000),
+                TimeUnit::Microsecond => Some(*ticks),
+                TimeUnit::Nanosecond => Some(ticks / 1_000),
+            };
+            match micros.and_then(DateTime::from_timestamp_micros) {
+                Some(time) => JsonValue::String(time.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                None => JsonValue::String(format!("{:?}", value)),
+            }
+        }
+        Value::List(values) | Value::Array(values) => JsonValue::Array(values.iter().map(to_json).collect()),
+        Value::Union(value) => to_json(value),
+        other => JsonValue::String(format!("{:?}", other)),
+    }
+}
+
+// Writes the result set to a Parquet file by loading it into an in-memory DuckDB table
+// and using COPY, so column types survive the round trip
+fn write_parquet(path: &str, result: &ResultSet) -> Result<(), Error> {
+    let conn = Connection::open_in_memory()?;
+
+    let columns: Vec<String> = result
+        .headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            let sql_type = result
+                .rows
+                .iter()
+                .map(|row| &row[i])
+                .find(|value| !matches!(value, Value::Null))
+                .map_or_else(|| "VARCHAR".to_string(), sql_type_of);
+            format!("\"{}\" {}", header.replace('"', "\"\""), sql_type)
+        })
+        .collect();
+    conn.execute_batch(&format!("CREATE TABLE export ({})", columns.join(", ")))?;
+
+    let mut appender = conn.appender("export")?;
+    for row in &result.rows {
+        let values: Vec<Value> = row
+            .iter()
+            .map(|value| match value {
+                // The appender can't take decimals, so they go as text for DuckDB to cast
+                Value::Decimal(d) => Value::Text(d.to_string()),
+                _ if sql_type_of(value) == "VARCHAR" && !matches!(value, Value::Text(_) | Value::Null) => {
+                    Value::Text(format_value(value))
+                }
+                _ => value.clone(),
+            })
+            .collect();
+        appender.append_row(appender_params_from_iter(values))?;
+    }
+    appender.flush()?;
+    drop(appender);
+
+    conn.execute_batch(&format!(
+        "COPY export TO '{}' (FORMAT PARQUET)",
+        path.replace('\'', "''")
+    ))?;
+    Ok(())
+}
+
+fn sql_type_of(value: &Value) -> String {
+    match value {
+        Value::Boolean(_) => "BOOLEAN".to_string(),
+        Value::TinyInt(_) | Value::SmallInt(_) | Value::Int(_) | Value::BigInt(_) => "BIGINT".to_string(),
+        Value::UTinyInt(_) | Value::USmallInt(_) | Value::UInt(_) => "BIGINT".to_string(),
+        Value::Float(_) | Value::Double(_) => "DOUBLE".to_string(),
+        Value::Decimal(d) => format!("DECIMAL(38, {})", d.scale()),
+        Value::Date32(_) => "DATE".to_string(),
+        _ => "VARCHAR".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates_across_the_epoch_leap_days_and_centuries() {
+        let date = |days| to_json(&Value::Date32(days));
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(-1), "1969-12-31");
+        assert_eq!(date(19_782), "2024-02-29");
+        assert_eq!(date(11_017), "2000-03-01");
+        assert_eq!(date(-25_508), "1900-03-01");
+    }
+
+    #[test]
+    fn formats_timestamps_in_any_unit() {
+        let leap_day_noon = 19_782 * 86_400 + 12 * 3600 + 34 * 60 + 56;
+        assert_eq!(to_json(&Value::Timestamp(TimeUnit::Second, leap_day_noon)), "2024-02-29T12:34:56");
+        assert_eq!(
+            to_json(&Value::Timestamp(TimeUnit::Nanosecond, leap_day_noon * 1_000_000_000 + 999)),
+            "2024-02-29T12:34:56"
+        );
+        assert_eq!(to_json(&Value::Timestamp(TimeUnit::Microsecond, -1)), "1969-12-31T23:59:59");
+    }
+}
@@ -1,13 +1,18 @@
 use std::fmt;
+use chrono::NaiveDate;
+use duckdb::Row;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use crate::row::{get_decimal, FromRow};
 
-#[derive(Debug,Clone)]
-pub(crate) struct ParcelRecord {
-    pub id: Option<f64>,
+#[derive(Debug, Clone, Serialize)]
+pub struct ParcelRecord {
+    pub id: Option<i64>,
     pub clean_parcel: Option<String>,
-    pub county_code: Option<f64>,
+    pub county_code: Option<i32>,
     pub county_name: Option<String>,
     pub parcel_id: Option<String>,
-    pub district_code: Option<f64>,
+    pub district_code: Option<i32>,
     pub district_name: Option<String>,
     pub map: Option<String>,
     pub parcel_number: Option<String>,
@@ -16,9 +21,11 @@ pub(crate) struct ParcelRecord {
     pub legal_description_1: Option<String>,
     pub legal_description_2: Option<String>,
     pub full_legal_description: Option<String>,
-    pub deeded_acre: Option<f64>,
-    pub calculated_acre: Option<f64>,
-    pub tax_year: Option<f64>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub deeded_acre: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub calculated_acre: Option<Decimal>,
+    pub tax_year: Option<i32>,
     pub tax_district: Option<String>,
     pub tax_class: Option<String>,
     pub deed_book: Option<String>,
@@ -43,7 +50,7 @@ pub(crate) struct ParcelRecord {
     pub full_new_owner: Option<String>,
     pub new_deed_book: Option<String>,
     pub new_deed_page: Option<String>,
-    pub physical_number: Option<f64>,
+    pub physical_number: Option<i32>,
     pub physical_direction: Option<String>,
     pub physical_street: Option<String>,
     pub physical_suffix: Option<String>,
@@ -56,32 +63,37 @@ pub(crate) struct ParcelRecord {
     pub hazard_occupancy: Option<String>,
     pub land_use: Option<String>,
     pub land_use_code: Option<String>,
-    pub year_built: Option<f64>,
+    pub year_built: Option<i32>,
     pub grade: Option<String>,
     pub style_code: Option<String>,
     pub style_description: Option<String>,
-    pub commercial: Option<f64>,
+    pub commercial: Option<i32>,
     pub stories: Option<f64>,
     pub commercial_type_1: Option<String>,
     pub basement_type: Option<String>,
     pub exterior_wall: Option<String>,
     pub exterior_1: Option<String>,
     pub construction: Option<String>,
-    pub total_rooms: Option<f64>,
+    pub total_rooms: Option<i32>,
     pub use_type: Option<String>,
-    pub business_license: Option<f64>,
+    pub business_license: Option<i64>,
     pub structure_area: Option<f64>,
     pub cubic_feet: Option<f64>,
-    pub units: Option<f64>,
-    pub commercial_type_2: Option<f64>,
-    pub card: Option<f64>,
-    pub cards: Option<f64>,
-    pub dwelling_value: Option<f64>,
-    pub commercial_type_3: Option<f64>,
-    pub other_building: Option<f64>,
-    pub land_appraised: Option<f64>,
-    pub building_appraised: Option<f64>,
-    pub total_appraised: Option<f64>,
+    pub units: Option<i32>,
+    pub commercial_type_2: Option<i32>,
+    pub card: Option<i32>,
+    pub cards: Option<i32>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub dwelling_value: Option<Decimal>,
+    pub commercial_type_3: Option<i32>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub other_building: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub land_appraised: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub building_appraised: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub total_appraised: Option<Decimal>,
     pub sams_address: Option<String>,
     pub sams_city: Option<String>,
     pub sams_state: Option<String>,
@@ -95,10 +107,12 @@ pub(crate) struct ParcelRecord {
     pub alternate_unit_type: Option<String>,
     pub alternate_unit_id: Option<String>,
     pub flood_risks: Option<String>,
-    pub oby_count: Option<f64>,
-    pub sale_price: Option<f64>,
+    pub oby_count: Option<i32>,
+    #[serde(with = "rust_decimal::serde::float_option")]
+    pub sale_price: Option<Decimal>,
+    pub sale_date: Option<NaiveDate>,
     pub developer_id: Option<String>,
-    pub building_permits: Option<f64>
+    pub building_permits: Option<i32>
 }
 
 // Implement Display for ParcelRecord
@@ -107,3 +121,109 @@ impl fmt::Display for ParcelRecord {
         write!(f, "{}", self.id.map_or("".to_string(), |v| v.to_string()))
     }
 }
+
+// Read the record back by column name, so it decodes from `parcel` or `parcel_summary`
+impl FromRow for ParcelRecord {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self> {
+        Ok(ParcelRecord {
+            id: row.get("id")?,
+            clean_parcel: row.get("clean_parcel")?,
+            county_code: row.get("county_code")?,
+            county_name: row.get("county_name")?,
+            parcel_id: row.get("parcel_id")?,
+            district_code: row.get("district_code")?,
+            district_name: row.get("district_name")?,
+            map: row.get("map")?,
+            parcel_number: row.get("parcel_number")?,
+            suffix: row.get("suffix")?,
+            legal_description: row.get("legal_description")?,
+            legal_description_1: row.get("legal_description_1")?,
+            legal_description_2: row.get("legal_description_2")?,
+            full_legal_description: row.get("full_legal_description")?,
+            deeded_acre: get_decimal(row, "deeded_acre")?,
+            calculated_acre: get_decimal(row, "calculated_acre")?,
+            tax_year: row.get("tax_year")?,
+            tax_district: row.get("tax_district")?,
+            tax_class: row.get("tax_class")?,
+            deed_book: row.get("deed_book")?,
+            deed_page: row.get("deed_page")?,
+            property_class: row.get("property_class")?,
+            property_type: row.get("property_type")?,
+            owner_1: row.get("owner_1")?,
+            owner_2: row.get("owner_2")?,
+            full_owner_name: row.get("full_owner_name")?,
+            owner_address: row.get("owner_address")?,
+            owner_address_1: row.get("owner_address_1")?,
+            owner_address_2: row.get("owner_address_2")?,
+            owner_city: row.get("owner_city")?,
+            owner_state: row.get("owner_state")?,
+            owner_zip: row.get("owner_zip")?,
+            care_of: row.get("care_of")?,
+            full_owner_address: row.get("full_owner_address")?,
+            new_owner: row.get("new_owner")?,
+            new_owner_address: row.get("new_owner_address")?,
+            new_owner_address_1: row.get("new_owner_address_1")?,
+            new_owner_address_2: row.get("new_owner_address_2")?,
+            full_new_owner: row.get("full_new_owner")?,
+            new_deed_book: row.get("new_deed_book")?,
+            new_deed_page: row.get("new_deed_page")?,
+            physical_number: row.get("physical_number")?,
+            physical_direction: row.get("physical_direction")?,
+            physical_street: row.get("physical_street")?,
+            physical_suffix: row.get("physical_suffix")?,
+            physical_unit_type: row.get("physical_unit_type")?,
+            physical_city: row.get("physical_city")?,
+            physical_zip: row.get("physical_zip")?,
+            physical_unit_id: row.get("physical_unit_id")?,
+            full_physical_address: row.get("full_physical_address")?,
+            occupancy_description: row.get("occupancy_description")?,
+            hazard_occupancy: row.get("hazard_occupancy")?,
+            land_use: row.get("land_use")?,
+            land_use_code: row.get("land_use_code")?,
+            year_built: row.get("year_built")?,
+            grade: row.get("grade")?,
+            style_code: row.get("style_code")?,
+            style_description: row.get("style_description")?,
+            commercial: row.get("commercial")?,
+            stories: row.get("stories")?,
+            commercial_type_1: row.get("commercial_type_1")?,
+            basement_type: row.get("basement_type")?,
+            exterior_wall: row.get("exterior_wall")?,
+            exterior_1: row.get("exterior_1")?,
+            construction: row.get("construction")?,
+            total_rooms: row.get("total_rooms")?,
+            use_type: row.get("use_type")?,
+            business_license: row.get("business_license")?,
+            structure_area: row.get("structure_area")?,
+            cubic_feet: row.get("cubic_feet")?,
+            units: row.get("units")?,
+            commercial_type_2: row.get("commercial_type_2")?,
+            card: row.get("card")?,
+            cards: row.get("cards")?,
+            dwelling_value: get_decimal(row, "dwelling_value")?,
+            commercial_type_3: row.get("commercial_type_3")?,
+            other_building: get_decimal(row, "other_building")?,
+            land_appraised: get_decimal(row, "land_appraised")?,
+            building_appraised: get_decimal(row, "building_appraised")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+            sams_address: row.get("sams_address")?,
+            sams_city: row.get("sams_city")?,
+            sams_state: row.get("sams_state")?,
+            sams_zip: row.get("sams_zip")?,
+            pre_address_number: row.get("pre_address_number")?,
+            address_number: row.get("address_number")?,
+            address_number_suffix: row.get("address_number_suffix")?,
+            full_name: row.get("full_name")?,
+            unit_type: row.get("unit_type")?,
+            unit_id: row.get("unit_id")?,
+            alternate_unit_type: row.get("alternate_unit_type")?,
+            alternate_unit_id: row.get("alternate_unit_id")?,
+            flood_risks: row.get("flood_risks")?,
+            oby_count: row.get("oby_count")?,
+            sale_price: get_decimal(row, "sale_price")?,
+            sale_date: row.get("sale_date")?,
+            developer_id: row.get("developer_id")?,
+            building_permits: row.get("building_permits")?,
+        })
+    }
+}
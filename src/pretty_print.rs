@@ -1,121 +1,312 @@
-use duckdb::Connection;
-use prettytable::{format, Cell, Row, Table};
-use crate::parcel_record::ParcelRecord;
-
-// A generic function to pretty print any tabular data
-pub fn pretty_print_table<T>(
-    headers: Vec<&str>,
-    records: &[T],
-    get_row_data: impl Fn(&T) -> Vec<String>,
-) {
-    // Use prettytable to display the data
-    let mut table = Table::new();
-
-    // Set table format
-    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-
-    // Add headers
-    table.set_titles(Row::new(
-        headers.into_iter().map(|h| Cell::new(h)).collect(),
-    ));
-
-    // Add rows to the table
-    for record in records {
-        let row_data = get_row_data(record);
-        let cells: Vec<Cell> = row_data.into_iter().map(|data| Cell::new(&data)).collect();
-        table.add_row(Row::new(cells));
+use std::env;
+use std::io::{self, IsTerminal};
+use clap::ValueEnum;
+use duckdb::types::Value;
+use serde::Deserialize;
+use terminal_size::Width;
+use crate::database::Database;
+use crate::output::{format_value, ResultSet};
+use crate::error::Error;
+
+pub fn print_parcel_table_schema(db: &Database) -> Result<(), Error> {
+    let mut stmt = db.prepare("PRAGMA table_info('parcel')")?;
+    let mut rows = stmt.query([])?;
+
+    println!("Schema of 'parcel' table:");
+    while let Some(row) = rows.next()? {
+        let cid: i64 = row.get(0)?;
+        let name: String = row.get(1)?;
+        let type_: String = row.get(2)?;
+        println!("Column ID: {}, Name: {}, Type: {}", cid, name, type_);
     }
 
-    // Print the table
-    table.printstd();
+    Ok(())
+}
+
+/// How `render_result` draws a table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TableStyle {
+    /// Columns separated by `|` with a rule under the header
+    #[default]
+    Plain,
+    /// GitHub-flavoured Markdown, ready to paste into an issue or wiki
+    Markdown,
+    /// Box-drawing characters around every cell
+    Boxed,
+    /// Space-separated columns with no rules
+    Compact,
+}
+
+/// Layout options for `render_result`.
+#[derive(Debug, Clone, Default)]
+pub struct TableOptions {
+    pub style: TableStyle,
+    /// Longest a column may be before its cells are cut short with an ellipsis
+    pub max_column_width: Option<usize>,
+    /// Width the whole table is squeezed into by narrowing its widest columns
+    pub max_width: Option<usize>,
 }
 
+impl TableOptions {
+    /// Options that fit tables to the terminal standard output is attached to, if any.
+    /// `COLUMNS` overrides the detected width.
+    pub fn for_terminal(style: TableStyle, max_column_width: Option<usize>) -> Self {
+        let max_width = env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .or_else(|| {
+                io::stdout()
+                    .is_terminal()
+                    .then(terminal_size::terminal_size)
+                    .flatten()
+                    .map(|(Width(width), _)| width as usize)
+            });
+        TableOptions {
+            style,
+            max_column_width,
+            max_width,
+        }
+    }
+}
+
+/// How a column's values are displayed in tables and HTML reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnFormat {
+    /// Shown as is and left-aligned, even when numeric
+    Text,
+    /// Thousands separators
+    Number,
+    /// A dollar amount with thousands separators
+    Currency,
+    /// A whole number without separators
+    Year,
+    /// A percentage with one decimal place
+    Percent,
+}
+
+impl ColumnFormat {
+    /// Guesses the format from a column header when none is given. Value columns get
+    /// currency formatting; years are numbers that must not get separators.
+    pub fn from_header(header: &str) -> Self {
+        let header = header.to_lowercase();
+        if ["appraised", "value", "price", "per acre", "per sq ft", "/acre", "/sq ft"]
+            .iter()
+            .any(|word| header.contains(word))
+        {
+            ColumnFormat::Currency
+        } else if header.contains("year") || header.contains("build") {
+            ColumnFormat::Year
+        } else {
+            ColumnFormat::Number
+        }
+    }
+}
+
+/// Formats a value for display in the given format.
+pub fn display_value(format: ColumnFormat, value: &Value) -> String {
+    format_cell(format, value).0
+}
+
+// Returns the display text and whether the cell is numeric, which right-aligns it
+fn format_cell(format: ColumnFormat, value: &Value) -> (String, bool) {
+    let number = match value {
+        Value::TinyInt(v) => Some((*v as f64, 0)),
+        Value::SmallInt(v) => Some((*v as f64, 0)),
+        Value::Int(v) => Some((*v as f64, 0)),
+        Value::BigInt(v) => Some((*v as f64, 0)),
+        Value::UTinyInt(v) => Some((*v as f64, 0)),
+        Value::USmallInt(v) => Some((*v as f64, 0)),
+        Value::UInt(v) => Some((*v as f64, 0)),
+        Value::UBigInt(v) => Some((*v as f64, 0)),
+        Value::Float(v) => Some((*v as f64, 2)),
+        Value::Double(v) => Some((*v, 2)),
+        // As many places as the value has, so acreage isn't padded out to its column's scale
+        Value::Decimal(v) => v.to_string().parse().ok().map(|n| (n, v.normalize().scale() as usize)),
+        _ => None,
+    };
+    match (number, format) {
+        (None, _) | (_, ColumnFormat::Text) => (format_value(value), false),
+        (Some((v, _)), ColumnFormat::Year) => (format!("{:.0}", v), true),
+        (Some((v, _)), ColumnFormat::Currency) => (format_currency(v), true),
+        (Some((v, _)), ColumnFormat::Percent) => (format!("{}%", format_number(v, 1)), true),
+        (Some((v, decimals)), ColumnFormat::Number) => (format_number(v, decimals), true),
+    }
+}
 
-pub fn pretty_print_parcel_records(records: &[ParcelRecord]) {
-    // Define headers specific to ParcelRecord for use in the table
-    let headers = vec![
-        "ID",
-        "Owner",
-        "Parcel ID",
-        "Deeded Acre",
-        "Land Use",
-        "Land Appraised",
-        "Building Appraised",
-        "Total Appraised",
-    ];
-
-    // Callback function to get row data for ParcelRecord
-    let get_row_data = |record: &ParcelRecord| -> Vec<String> {
-        vec![
-            record.id.map_or("".to_string(), |v| v.to_string()),
-            record.full_owner_name.as_deref().unwrap_or("").to_string(),
-            record.parcel_id.as_deref().unwrap_or("").to_string(),
-            record.deeded_acre
-                .map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.land_use.as_deref().unwrap_or("").to_string(),
-            record.land_appraised
-                .map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.building_appraised
-                .map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.total_appraised
-                .map_or("".to_string(), |v| format!("{:.2}", v)),
-        ]
+/// Formats a number with comma thousands separators, e.g. `1234567.8` as `1,234,567.80`.
+pub fn format_number(value: f64, decimals: usize) -> String {
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (whole, fraction) = match formatted.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (formatted.as_str(), None),
     };
 
-    // Use the generic function to pretty print the parcel records
-    pretty_print_table(headers, &records, get_row_data);
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+    if value < 0.0 && grouped.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        grouped.insert(0, '-');
+    }
+    grouped
 }
 
+/// Formats a dollar amount, e.g. `-1234.5` as `-$1,234.50`.
+pub fn format_currency(value: f64) -> String {
+    let amount = format_number(value, 2);
+    match amount.strip_prefix('-') {
+        Some(amount) => format!("-${}", amount),
+        None => format!("${}", amount),
+    }
+}
+
+/// Renders a result set as a text table in the given style.
+///
+/// Numeric columns are right-aligned. Cells longer than `max_column_width` are cut short
+/// with an ellipsis, and if the table is still wider than `max_width` its widest columns
+/// are narrowed until it fits.
+pub fn render_result(result: &ResultSet, options: &TableOptions) -> String {
+    let formats: Vec<ColumnFormat> = (0..result.headers.len()).map(|i| result.column_format(i)).collect();
+    let rows: Vec<Vec<(String, bool)>> = result
+        .rows
+        .iter()
+        .map(|row| row.iter().zip(&formats).map(|(value, format)| format_cell(*format, value)).collect())
+        .collect();
+
+    // A column is right-aligned when every non-empty cell in it is numeric
+    let right_aligned: Vec<bool> = (0..result.headers.len())
+        .map(|i| {
+            rows.iter().any(|row| row[i].1)
+                && rows.iter().all(|row| row[i].1 || row[i].0.is_empty())
+        })
+        .collect();
 
-pub fn pretty_print_5(records: &mut Vec<ParcelRecord>) {
-    // Use prettytable to display the data
-    let mut table = Table::new();
-
-    // Set table format
-    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-
-    // Add headers
-    table.set_titles(Row::new(vec![
-        Cell::new("ID"),
-        Cell::new("Owner"),
-        Cell::new("Parcel ID"),
-        Cell::new("Deeded Acre"),
-        Cell::new("Land Use"),
-        Cell::new("Land Appraised"),
-        Cell::new("Building Appraised"),
-        Cell::new("Total Appraised"),
-    ]));
-
-    // Add first 5 records to the table
-    for record in records.iter().take(5) {
-        table.add_row(Row::new(vec![
-            Cell::new(&record.id.map_or("".to_string(), |v| v.to_string())),
-            Cell::new(record.full_owner_name.as_deref().unwrap_or("")),
-            Cell::new(record.parcel_id.as_deref().unwrap_or("")),
-            Cell::new(&record.deeded_acre.map_or("".to_string(), |v| format!("{:.2}", v))),
-            Cell::new(record.land_use.as_deref().unwrap_or("")),
-            Cell::new(&record.land_appraised.map_or("".to_string(), |v| format!("{:.2}", v))),
-            Cell::new(&record.building_appraised.map_or("".to_string(), |v| format!("{:.2}", v))),
-            Cell::new(&record.total_appraised.map_or("".to_string(), |v| format!("{:.2}", v))),
-        ]));
+    let mut widths: Vec<usize> = result
+        .headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].0.chars().count())
+                .chain([header.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    // Only text columns are shortened; a truncated number would be misleading
+    if let Some(limit) = options.max_column_width {
+        for (width, right) in widths.iter_mut().zip(&right_aligned) {
+            if !right {
+                *width = (*width).min(limit.max(1));
+            }
+        }
     }
+    if let Some(max_width) = options.max_width {
+        fit_widths(&mut widths, &right_aligned, max_width, options.style);
+    }
+
+    let header: Vec<String> = result
+        .headers
+        .iter()
+        .zip(&widths)
+        .map(|(header, width)| truncate(header, *width))
+        .collect();
+    let body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().zip(&widths).map(|((text, _), width)| truncate(text, *width)).collect())
+        .collect();
+
+    let pad = |text: &str, i: usize| {
+        if right_aligned[i] {
+            format!("{:>width$}", text, width = widths[i])
+        } else {
+            format!("{:<width$}", text, width = widths[i])
+        }
+    };
+    let line = |cells: &[String], left: &str, separator: &str, right: &str| {
+        let padded: Vec<String> = cells.iter().enumerate().map(|(i, cell)| pad(cell, i)).collect();
+        format!("{}{}{}\n", left, padded.join(separator), right)
+    };
+    let rule = |left: &str, fill: &str, junction: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| fill.repeat(width + 2)).collect();
+        format!("{}{}{}\n", left, segments.join(junction), right)
+    };
 
-    // Print the table
-    table.printstd();
+    let mut out = String::new();
+    match options.style {
+        TableStyle::Plain => {
+            out.push_str(&line(&header, " ", " | ", " "));
+            out.push_str(&rule("", "-", "+", ""));
+            body.iter().for_each(|row| out.push_str(&line(row, " ", " | ", " ")));
+        }
+        TableStyle::Markdown => {
+            let escaped = |cells: &[String]| -> Vec<String> {
+                cells.iter().map(|cell| cell.replace('|', "\\|")).collect()
+            };
+            out.push_str(&line(&escaped(&header), "| ", " | ", " |"));
+            let markers: Vec<String> = widths
+                .iter()
+                .zip(&right_aligned)
+                .map(|(width, right)| {
+                    let dashes = "-".repeat((*width).max(3) - usize::from(*right));
+                    if *right { format!("{}:", dashes) } else { dashes }
+                })
+                .collect();
+            out.push_str(&format!("| {} |\n", markers.join(" | ")));
+            body.iter().for_each(|row| out.push_str(&line(&escaped(row), "| ", " | ", " |")));
+        }
+        TableStyle::Boxed => {
+            out.push_str(&rule("┌", "─", "┬", "┐"));
+            out.push_str(&line(&header, "│ ", " │ ", " │"));
+            out.push_str(&rule("├", "─", "┼", "┤"));
+            body.iter().for_each(|row| out.push_str(&line(row, "│ ", " │ ", " │")));
+            out.push_str(&rule("└", "─", "┴", "┘"));
+        }
+        TableStyle::Compact => {
+            out.push_str(&line(&header, "", "  ", ""));
+            body.iter().for_each(|row| out.push_str(&line(row, "", "  ", "")));
+        }
+    }
+    out
 }
 
-pub fn print_parcel_table_schema() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = Connection::open("parcel_data.db")?;
-    let mut stmt = conn.prepare("PRAGMA table_info('parcel')")?;
-    let mut rows = stmt.query([])?;
+// Narrows the widest text columns one character at a time until the table fits, never
+// taking a column below a few characters
+fn fit_widths(widths: &mut [usize], right_aligned: &[bool], max_width: usize, style: TableStyle) {
+    const MIN_WIDTH: usize = 4;
+    let per_column = match style {
+        TableStyle::Compact => 2,
+        _ => 3,
+    };
+    let total = |widths: &[usize]| widths.iter().sum::<usize>() + widths.len() * per_column + 1;
 
-    println!("Schema of 'parcel' table:");
-    while let Some(row) = rows.next()? {
-        let cid: i64 = row.get(0)?;
-        let name: String = row.get(1)?;
-        let type_: String = row.get(2)?;
-        println!("Column ID: {}, Name: {}, Type: {}", cid, name, type_);
+    while total(widths) > max_width {
+        let widest = widths
+            .iter_mut()
+            .zip(right_aligned)
+            .filter(|(width, right)| !**right && **width > MIN_WIDTH)
+            .map(|(width, _)| width)
+            .max_by_key(|width| **width);
+        match widest {
+            Some(widest) => *widest -= 1,
+            None => break,
+        }
     }
+}
 
-    Ok(())
-}
\ No newline at end of file
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
@@ -1,180 +1,103 @@
-use duckdb::Connection;
-use crate::pretty_print::pretty_print_table;
-
-pub fn print_first_five_records() -> Result<(), Box<dyn std::error::Error>> {
-    // Open a connection to the DuckDB database
-    let conn = Connection::open("parcel_data.db")?;
-
-    // Prepare the SQL query
-    let mut stmt = conn.prepare("SELECT * FROM parcel LIMIT 5")?;
-
-    // Execute the query and obtain an iterator over the results
-    let mut rows = stmt.query([])?;
-
-    // Iterate over the rows and print each one
-    while let Some(row) = rows.next()? {
-        // Extract each column from the row
-        let col0: Option<f64> = row.get(0)?;
-        let col1: Option<String> = row.get(1)?;
-        let col2: Option<String> = row.get(2)?;
-        let col3: Option<f64> = row.get(3)?;
-        let col4: Option<String> = row.get(4)?;
-        let col5: Option<f64> = row.get(5)?;
-        let col6: Option<f64> = row.get(6)?;
-        let col7: Option<f64> = row.get(7)?;
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Connection};
+use rust_decimal::Decimal;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::parcel_record::ParcelRecord;
+use crate::row::{get_decimal, query_as, FromRow};
+use crate::error::Error;
+
+/// A land use with the number and value of its parcels.
+struct LandUse {
+    land_use: Option<String>,
+    land_use_code: Option<String>,
+    parcel_count: i64,
+    total_appraised: Option<Decimal>,
+}
 
-        // Print the tuple of column values
-        println!(
-            "{:?}",
-            (col0, col1, col2, col3, col4, col5, col6, col7)
-        );
+impl FromRow for LandUse {
+    fn from_row(row: &duckdb::Row<'_>) -> duckdb::Result<Self> {
+        Ok(LandUse {
+            land_use: row.get("land_use")?,
+            land_use_code: row.get("land_use_code")?,
+            parcel_count: row.get("parcel_count")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+        })
     }
-
-    Ok(())
 }
 
-pub fn get_names_with_most_parcels(limit: usize) -> Result<(), Box<dyn std::error::Error>> {
-    // Open a connection to the DuckDB database
-    let conn = Connection::open("parcel_data.db")?;
-
+/// Returns every land use with its parcel count and total appraised value, most common first.
+pub fn get_land_uses(
+    conn: &Connection,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
     // Prepare the SQL query
-    let query = format!(
-        "SELECT
-            full_owner_name,
-            COUNT(*) AS parcel_count
+    let query = "
+        SELECT
+            land_use,
+            ANY_VALUE(land_use_code) AS land_use_code,
+            COUNT(*) AS parcel_count,
+            SUM(total_appraised) AS total_appraised
         FROM
-            parcel
+            parcel_summary
         GROUP BY
-            full_owner_name
+            land_use
         ORDER BY
-            parcel_count DESC
-        LIMIT
-            {}",
-        limit
-    );
-
-    // Prepare and execute the query
-    let mut stmt = conn.prepare(&query)?;
-    let mut rows = stmt.query([])?;
-
-    // Collect records into a vector
-    let mut records = Vec::new();
-    while let Some(row) = rows.next()? {
-        let full_owner_name: Option<String> = row.get(0)?;
-        let parcel_count: i64 = row.get(1)?;
+            parcel_count DESC,
+            land_use
+    ";
 
-        records.push((full_owner_name, parcel_count));
-    }
+    // Restrict the query to the filtered parcels, then run it
+    let (query, values) = filter.scope(query);
+    let records: Vec<LandUse> = query_as(conn, &query, params_from_iter(values))?;
 
     // Define headers for printing
-    let headers = vec!["Owner", "Parcel Count"];
+    let headers = vec!["Land Use", "Code", "Parcels", "Total Appraised"];
 
     // Callback to generate row data
-    let get_row_data = |record: &(Option<String>, i64)| -> Vec<String> {
+    let get_row_data = |record: &LandUse| -> Vec<Value> {
         vec![
-            record.0.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.1.to_string(),
+            nullable(record.land_use.clone()),
+            nullable(record.land_use_code.clone()),
+            record.parcel_count.into(),
+            nullable_decimal(record.total_appraised),
         ]
     };
 
-    // Print the table using the generic function
-    pretty_print_table(headers, &records, get_row_data);
-
-    Ok(())
+    Ok(ResultSet::new(headers, &records, get_row_data))
 }
 
-pub fn get_total_appraised_value_per_owner(limit: usize) -> Result<(), Box<dyn std::error::Error>> {
-    // Open a connection to the DuckDB database
-    let conn = Connection::open("parcel_data.db")?;
-
+/// Returns the parcels with the given land use, matched case-insensitively, highest
+/// appraised value first.
+pub fn get_parcels_by_land_use(
+    conn: &Connection,
+    land_use_type: &str,
+    limit: usize,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
     // Prepare the SQL query
-    let query = format!(
-        "SELECT
-            full_owner_name,
-            SUM(total_appraised) AS total_appraised_value
+    let query = "
+        SELECT
+            *
         FROM
-            parcel
-        GROUP BY
-            full_owner_name
+            parcel_summary
+        WHERE
+            land_use ILIKE ?
         ORDER BY
-            total_appraised_value DESC
+            total_appraised DESC NULLS LAST
         LIMIT
-            {}",
-        limit
-    );
-
-    // Prepare and execute the query
-    let mut stmt = conn.prepare(&query)?;
-    let mut rows = stmt.query([])?;
-
-    // Collect results into a vector of tuples
-    let mut results = Vec::new();
-    while let Some(row) = rows.next()? {
-        let full_owner_name: Option<String> = row.get(0)?;
-        let total_appraised_value: Option<f64> = row.get(1)?;
-        results.push((
-            full_owner_name.unwrap_or_else(|| "Unknown".to_string()),
-            total_appraised_value.unwrap_or(0.0),
-        ));
-    }
-
-    // Define headers for printing
-    let headers = vec!["Owner", "Total Appraised Value"];
-
-    // Callback to convert the result tuple into a vector of strings
-    let get_row_data = |record: &(String, f64)| -> Vec<String> {
-        vec![
-            record.0.clone(),
-            format!("{:.2}", record.1),
-        ]
-    };
-
-    // Pretty print the table
-    pretty_print_table(headers, &results, get_row_data);
-
-    Ok(())
-}
-
-pub fn get_parcels_by_land_use(land_use_type: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // Open a connection to the DuckDB database
-    let conn = Connection::open("parcel_data.db")?;
-
-    // Prepare the SQL query
-    let query = "SELECT * FROM parcel WHERE land_use = ?";
-
-    // Prepare and execute the query
-    let mut stmt = conn.prepare(query)?;
-    let mut rows = stmt.query([land_use_type])?;
-
-    // Collect records into a vector
-    let mut records = Vec::new();
-    while let Some(row) = rows.next()? {
-        let id: Option<f64> = row.get(0)?;
-        let full_owner_name: Option<String> = row.get(1)?;
-        let parcel_id: Option<String> = row.get(2)?;
-        let deeded_acre: Option<f64> = row.get(3)?;
-        let land_use: Option<String> = row.get(4)?;
-        let land_appraised: Option<f64> = row.get(5)?;
-        let building_appraised: Option<f64> = row.get(6)?;
-        let total_appraised: Option<f64> = row.get(7)?;
+            ?
+    ";
 
-        records.push((
-            id,
-            full_owner_name,
-            parcel_id,
-            deeded_acre,
-            land_use,
-            land_appraised,
-            building_appraised,
-            total_appraised,
-        ));
-    }
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.extend([Value::Text(land_use_type.to_string()), Value::BigInt(limit as i64)]);
+    let records: Vec<ParcelRecord> = query_as(conn, &query, params_from_iter(values))?;
 
     // Define headers for printing
     let headers = vec![
-        "ID",
-        "Owner",
         "Parcel ID",
+        "Owner",
         "Deeded Acre",
         "Land Use",
         "Land Appraised",
@@ -183,133 +106,76 @@ pub fn get_parcels_by_land_use(land_use_type: &str) -> Result<(), Box<dyn std::e
     ];
 
     // Callback to generate row data
-    let get_row_data = |record: &(
-        Option<f64>,
-        Option<String>,
-        Option<String>,
-        Option<f64>,
-        Option<String>,
-        Option<f64>,
-        Option<f64>,
-        Option<f64>,
-    )| -> Vec<String> {
+    let get_row_data = |record: &ParcelRecord| -> Vec<Value> {
         vec![
-            record.0.map_or("".to_string(), |v| v.to_string()),
-            record.1.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.2.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.3.map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.4.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.5.map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.6.map_or("".to_string(), |v| format!("{:.2}", v)),
-            record.7.map_or("".to_string(), |v| format!("{:.2}", v)),
+            nullable(record.parcel_id.clone()),
+            nullable(record.full_owner_name.clone()),
+            nullable_decimal(record.deeded_acre),
+            nullable(record.land_use.clone()),
+            nullable_decimal(record.land_appraised),
+            nullable_decimal(record.building_appraised),
+            nullable_decimal(record.total_appraised),
         ]
     };
 
-    // Print the table using the generic function
-    pretty_print_table(headers, &records, get_row_data);
-
-    Ok(())
+    Ok(ResultSet::new(headers, &records, get_row_data))
 }
 
-pub fn find_potential_redemption_properties() -> Result<(), Box<dyn std::error::Error>> {
-    // Open a connection to the DuckDB database
-    let conn = Connection::open("parcel_data.db")?;
-
-    // Prepare the SQL query to find parcels with low appraised values
+/// Returns the most recent tax year's record for a parcel, if there is one.
+pub fn get_parcel(conn: &Connection, parcel_id: &str) -> duckdb::Result<Option<ParcelRecord>> {
     let query = "
         SELECT
-            id,
-            full_owner_name,
-            parcel_id,
-            total_appraised,
-            owner_address,
-            owner_city,
-            owner_state,
-            physical_city,
-            land_use
+            *
         FROM
-            parcel
+            parcel_summary
         WHERE
-            total_appraised < 50000
-            AND (owner_address IS NULL OR owner_address = '')
-            AND (full_owner_name IS NULL OR full_owner_name = '')
+            parcel_id = ?
         ORDER BY
-            total_appraised ASC
+            tax_year DESC NULLS LAST
         LIMIT
-            100
+            1
     ";
+    let records: Vec<ParcelRecord> = query_as(conn, query, [parcel_id])?;
+    Ok(records.into_iter().next())
+}
 
-    // Prepare and execute the query
-    let mut stmt = conn.prepare(query)?;
-    let mut rows = stmt.query([])?;
-
-    // Collect records into a vector
-    let mut records = Vec::new();
-    while let Some(row) = rows.next()? {
-        let id: Option<f64> = row.get(0)?;
-        let full_owner_name: Option<String> = row.get(1)?;
-        let parcel_id: Option<String> = row.get(2)?;
-        let total_appraised: Option<f64> = row.get(3)?;
-        let owner_address: Option<String> = row.get(4)?;
-        let owner_city: Option<String> = row.get(5)?;
-        let owner_state: Option<String> = row.get(6)?;
-        let physical_city: Option<String> = row.get(7)?;
-        let land_use: Option<String> = row.get(8)?;
-
-        records.push((
-            id,
-            full_owner_name,
-            parcel_id,
-            total_appraised,
-            owner_address,
-            owner_city,
-            owner_state,
-            physical_city,
-            land_use,
-        ));
-    }
-
-    // Define headers for printing
-    let headers = vec![
-        "ID",
-        "Owner",
-        "Parcel ID",
-        "Total Appraised",
-        "Owner Address",
-        "Owner City",
-        "Owner State",
-        "Physical City",
-        "Land Use",
-    ];
-
-    // Callback to generate row data
-    let get_row_data = |record: &(
-        Option<f64>,
-        Option<String>,
-        Option<String>,
-        Option<f64>,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-    )| -> Vec<String> {
-        vec![
-            record.0.map_or_else(|| "".to_string(), |v| v.to_string()),
-            record.1.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.2.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.3.map_or_else(|| "0.00".to_string(), |v| format!("{:.2}", v)),
-            record.4.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.5.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.6.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.7.clone().unwrap_or_else(|| "Unknown".to_string()),
-            record.8.clone().unwrap_or_else(|| "Unknown".to_string()),
-        ]
-    };
-
-    // Print the table using the generic function
-    pretty_print_table(headers, &records, get_row_data);
+/// Returns owners whose name matches the case-insensitive `LIKE` pattern, in which `\`
+/// escapes a `%`, `_` or `\`, with their parcel count and total appraised value, largest
+/// holdings first. `offset` and `limit` page through the matches.
+pub fn search_owners(
+    conn: &Connection,
+    pattern: &str,
+    filter: &ParcelFilter,
+    offset: usize,
+    limit: usize,
+) -> Result<ResultSet, Error> {
+    // Prepare the SQL query
+    let query = "
+        SELECT
+            full_owner_name AS \"Owner\",
+            COUNT(*) AS \"Parcels\",
+            SUM(total_appraised) AS \"Total Appraised\"
+        FROM
+            parcel_summary
+        WHERE
+            full_owner_name ILIKE ? ESCAPE '\\'
+        GROUP BY
+            full_owner_name
+        ORDER BY
+            \"Total Appraised\" DESC NULLS LAST,
+            \"Owner\"
+        LIMIT
+            ?
+        OFFSET
+            ?
+    ";
 
-    Ok(())
+    // Restrict the query to the filtered parcels, then run it
+    let (query, mut values) = filter.scope(query);
+    values.extend([
+        Value::Text(pattern.to_string()),
+        Value::BigInt(limit as i64),
+        Value::BigInt(offset as i64),
+    ]);
+    Ok(ResultSet::query(conn, &query, params_from_iter(values))?)
 }
-
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Connection};
+use serde::Deserialize;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, ResultSet};
+use crate::pretty_print::ColumnFormat;
+use crate::error::{parse_input, Error};
+
+/// Reports compiled into the binary, as (name, SQL file) pairs.
+const BUILTIN_REPORTS: &[(&str, &str)] = &[
+    ("outliers", include_str!("../reports/outliers.sql")),
+    ("owners-by-parcels", include_str!("../reports/owners-by-parcels.sql")),
+    ("owners-by-value", include_str!("../reports/owners-by-value.sql")),
+    ("redemption", include_str!("../reports/redemption.sql")),
+    ("summary", include_str!("../reports/summary.sql")),
+    ("valuation", include_str!("../reports/valuation.sql")),
+];
+
+/// Metadata from the comment block at the top of a report's SQL file.
+///
+/// The block is TOML with every line prefixed by `--`:
+///
+/// ```sql
+/// -- title = "Owners with the most parcels"
+/// --
+/// -- [[param]]
+/// -- name = "limit"
+/// -- type = "integer"
+/// -- default = 7
+/// --
+/// -- [columns]
+/// -- "Parcel Count" = "number"
+/// SELECT ... LIMIT $limit
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportMeta {
+    pub title: String,
+    pub description: Option<String>,
+    /// Parameters the SQL refers to as `$name`, in the order they are listed
+    #[serde(default, rename = "param")]
+    pub params: Vec<ReportParam>,
+    /// Display formats for result columns, keyed by column name
+    #[serde(default)]
+    pub columns: HashMap<String, ColumnFormat>,
+}
+
+/// A typed report parameter.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParamType,
+    /// Value used when the parameter isn't given; without one it is `NULL`
+    pub default: Option<toml::Value>,
+    pub description: Option<String>,
+}
+
+/// The types a report parameter can take.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+}
+
+impl ParamType {
+    /// Parses a parameter value given on the command line or in a query string.
+    pub fn parse(&self, raw: &str) -> Result<Value, Error> {
+        Ok(match self {
+            ParamType::Integer => Value::BigInt(parse_input(raw)?),
+            ParamType::Float => Value::Double(parse_input(raw)?),
+            ParamType::Text => Value::Text(raw.to_string()),
+            ParamType::Boolean => Value::Boolean(parse_input(raw)?),
+        })
+    }
+
+    // Converts a TOML default into a bind value of this type
+    fn convert_toml(&self, value: &toml::Value) -> Result<Value, Error> {
+        Ok(match (self, value) {
+            (ParamType::Integer, toml::Value::Integer(v)) => Value::BigInt(*v),
+            (ParamType::Float, toml::Value::Float(v)) => Value::Double(*v),
+            (ParamType::Float, toml::Value::Integer(v)) => Value::Double(*v as f64),
+            (ParamType::Text, toml::Value::String(v)) => Value::Text(v.clone()),
+            (ParamType::Boolean, toml::Value::Boolean(v)) => Value::Boolean(*v),
+            _ => return Err(Error::Config(format!("default {} is not a valid {:?}", value, self))),
+        })
+    }
+}
+
+/// A report defined by a SQL file and its metadata.
+#[derive(Debug)]
+pub struct SavedReport {
+    pub name: String,
+    pub meta: ReportMeta,
+    sql: String,
+}
+
+impl SavedReport {
+    /// Parses a report from the contents of its SQL file.
+    pub fn parse(name: &str, source: &str) -> Result<SavedReport, Error> {
+        let header_lines = source
+            .lines()
+            .take_while(|line| line.trim_start().starts_with("--"))
+            .count();
+        let header: Vec<&str> = source
+            .lines()
+            .take(header_lines)
+            .map(|line| {
+                let line = line.trim_start().trim_start_matches("--");
+                line.strip_prefix(' ').unwrap_or(line)
+            })
+            .collect();
+        let sql: Vec<&str> = source.lines().skip(header_lines).collect();
+
+        let meta: ReportMeta = toml::from_str(&header.join("\n"))
+            .map_err(|e| Error::Config(format!("report '{}' has invalid metadata: {}", name, e)))?;
+        for param in &meta.params {
+            if let Some(default) = &param.default {
+                param
+                    .kind
+                    .convert_toml(default)
+                    .map_err(|e| Error::Config(format!("report '{}', parameter '{}': {}", name, param.name, e)))?;
+            }
+        }
+
+        Ok(SavedReport {
+            name: name.to_string(),
+            meta,
+            sql: sql.join("\n"),
+        })
+    }
+
+    /// Describes each of the report's parameters.
+    pub fn describe(&self) -> ResultSet {
+        let describe = |param: &ReportParam| -> Vec<Value> {
+            vec![
+                param.name.clone().into(),
+                format!("{:?}", param.kind).to_lowercase().into(),
+                nullable(param.default.as_ref().map(|default| default.to_string())),
+                nullable(param.description.clone()),
+            ]
+        };
+        ResultSet::new(
+            vec!["Parameter", "Type", "Default", "Description"],
+            &self.meta.params,
+            describe,
+        )
+    }
+
+    /// Whether the report takes a parameter called `name`.
+    pub fn has_param(&self, name: &str) -> bool {
+        self.meta.params.iter().any(|param| param.name == name)
+    }
+
+    /// Runs the report with the given parameter values, as unparsed strings keyed by name,
+    /// over the parcels matching `filter`. Parameters that aren't given take their defaults.
+    pub fn run(
+        &self,
+        conn: &Connection,
+        args: &HashMap<String, String>,
+        filter: &ParcelFilter,
+    ) -> Result<ResultSet, Error> {
+        self.execute(conn, args, filter, None)
+    }
+
+    /// Like [`run`](Self::run), but returns at most `limit` rows after skipping `offset`,
+    /// paging in SQL rather than over the whole result.
+    pub fn run_page(
+        &self,
+        conn: &Connection,
+        args: &HashMap<String, String>,
+        filter: &ParcelFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<ResultSet, Error> {
+        self.execute(conn, args, filter, Some((offset, limit)))
+    }
+
+    fn execute(
+        &self,
+        conn: &Connection,
+        args: &HashMap<String, String>,
+        filter: &ParcelFilter,
+        window: Option<(usize, usize)>,
+    ) -> Result<ResultSet, Error> {
+        if let Some(unknown) = args.keys().find(|name| !self.has_param(name)) {
+            return Err(Error::InvalidInput(format!("report '{}' has no parameter '{}'", self.name, unknown)));
+        }
+
+        let mut values = Vec::new();
+        for param in &self.meta.params {
+            let value = match (args.get(&param.name), &param.default) {
+                (Some(raw), _) => param
+                    .kind
+                    .parse(raw)
+                    .map_err(|e| Error::InvalidInput(format!("invalid value '{}' for '{}': {}", raw, param.name, e)))?,
+                (None, Some(default)) => param.kind.convert_toml(default)?,
+                (None, None) => Value::Null,
+            };
+            values.push(value);
+        }
+
+        let (sql, order) = self.positional_sql()?;
+        let (mut sql, mut bound) = filter.scope(&sql);
+        bound.extend(order.into_iter().map(|i| values[i].clone()));
+        if let Some((offset, limit)) = window {
+            sql = format!("SELECT * FROM (\n{}\n) LIMIT ? OFFSET ?", sql);
+            bound.extend([Value::BigInt(limit as i64), Value::BigInt(offset as i64)]);
+        }
+        let mut result = ResultSet::query(conn, &sql, params_from_iter(bound))?;
+        result.formats = result
+            .headers
+            .iter()
+            .map(|header| self.meta.columns.get(header).copied())
+            .collect();
+        Ok(result)
+    }
+
+    // Rewrites `$name` placeholders to `?`, leaving string literals, quoted identifiers and
+    // comments alone, so the SQL composes with `ParcelFilter::scope`. Also returns the
+    // declared index of the parameter behind each `?`, in order; a parameter used twice is
+    // bound twice.
+    fn positional_sql(&self) -> Result<(String, Vec<usize>), Error> {
+        let mut sql = String::with_capacity(self.sql.len());
+        let mut order: Vec<usize> = Vec::new();
+        let mut chars = self.sql.chars().peekable();
+        let mut quote: Option<char> = None;
+
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(open), _) if c == open => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                // Comments run to the end of the line or the closing `*/`, whatever they hold
+                (None, '-') if chars.peek() == Some(&'-') => {
+                    sql.push(c);
+                    for next in chars.by_ref() {
+                        sql.push(next);
+                        if next == '\n' {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                (None, '/') if chars.peek() == Some(&'*') => {
+                    sql.push(c);
+                    sql.extend(chars.next());
+                    let mut previous = ' ';
+                    for next in chars.by_ref() {
+                        sql.push(next);
+                        if previous == '*' && next == '/' {
+                            break;
+                        }
+                        previous = next;
+                    }
+                    continue;
+                }
+                (None, '$') if chars.peek().is_some_and(|next| next.is_alphabetic() || *next == '_') => {
+                    let mut name = String::new();
+                    while let Some(next) = chars.peek().filter(|next| next.is_alphanumeric() || **next == '_') {
+                        name.push(*next);
+                        chars.next();
+                    }
+                    let declared = self
+                        .meta
+                        .params
+                        .iter()
+                        .position(|param| param.name == name)
+                        .ok_or_else(|| Error::Config(format!("report '{}' uses undeclared parameter ${}", self.name, name)))?;
+                    order.push(declared);
+                    sql.push('?');
+                    continue;
+                }
+                (None, _) => {}
+            }
+            sql.push(c);
+        }
+        Ok((sql, order))
+    }
+}
+
+/// The saved reports available to the CLI, keyed by name.
+pub struct ReportRegistry {
+    reports: Vec<SavedReport>,
+}
+
+impl ReportRegistry {
+    /// Loads the built-in reports, then every `.sql` file in `dir`. A file named like a
+    /// built-in report replaces it.
+    pub fn load(dir: Option<&Path>) -> Result<ReportRegistry, Error> {
+        let mut registry = ReportRegistry { reports: Vec::new() };
+        for (name, source) in BUILTIN_REPORTS {
+            registry.insert(SavedReport::parse(name, source)?);
+        }
+
+        if let Some(dir) = dir {
+            for entry in fs::read_dir(dir).map_err(|e| Error::file(dir, e))? {
+                let path = entry.map_err(|e| Error::file(dir, e))?.path();
+                if path.extension().is_some_and(|extension| extension == "sql") {
+                    let name = path
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .ok_or_else(|| Error::Config(format!("report file name {} is not valid UTF-8", path.display())))?;
+                    registry.insert(SavedReport::parse(name, &fs::read_to_string(&path).map_err(|e| Error::file(&path, e))?)?);
+                }
+            }
+        }
+        Ok(registry)
+    }
+
+    fn insert(&mut self, report: SavedReport) {
+        self.reports.retain(|existing| existing.name != report.name);
+        self.reports.push(report);
+        self.reports.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Looks a report up by name.
+    pub fn get(&self, name: &str) -> Result<&SavedReport, Error> {
+        self.reports
+            .iter()
+            .find(|report| report.name == name)
+            .ok_or_else(|| Error::NotFound(format!("no report named '{}'; see `report --list`", name)))
+    }
+
+    /// Describes every report and its parameters.
+    pub fn list(&self) -> ResultSet {
+        let describe = |report: &SavedReport| -> Vec<Value> {
+            let params: Vec<String> = report
+                .meta
+                .params
+                .iter()
+                .map(|param| match &param.default {
+                    Some(default) => format!("{}={}", param.name, default),
+                    None => param.name.clone(),
+                })
+                .collect();
+            vec![
+                report.name.clone().into(),
+                report.meta.title.clone().into(),
+                params.join(" ").into(),
+            ]
+        };
+        ResultSet::new(vec!["Name", "Title", "Parameters"], &self.reports, describe)
+    }
+}
+
+/// Splits `KEY=VALUE` report arguments into a map.
+pub fn parse_args(args: &[String]) -> Result<HashMap<String, String>, Error> {
+    args.iter()
+        .map(|arg| {
+            arg.split_once('=')
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .ok_or_else(|| Error::InvalidInput(format!("expected KEY=VALUE, got '{}'", arg)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "\
+-- title = \"Test\"
+--
+-- [[param]]
+-- name = \"owner\"
+-- type = \"text\"
+--
+-- [[param]]
+-- name = \"limit\"
+-- type = \"integer\"
+-- default = 5
+";
+
+    fn positional(sql: &str) -> (String, Vec<usize>) {
+        SavedReport::parse("test", &format!("{}{}", HEADER, sql)).unwrap().positional_sql().unwrap()
+    }
+
+    #[test]
+    fn rewrites_parameters_in_declared_order() {
+        let (sql, order) = positional("SELECT * FROM t WHERE o = $owner OR p = $owner LIMIT $limit");
+        assert_eq!(sql, "SELECT * FROM t WHERE o = ? OR p = ? LIMIT ?");
+        assert_eq!(order, [0, 0, 1]);
+    }
+
+    #[test]
+    fn leaves_literals_and_quoted_identifiers_alone() {
+        let (sql, order) = positional("SELECT '$owner' AS \"$limit\", 'it''s' FROM t LIMIT $limit");
+        assert_eq!(sql, "SELECT '$owner' AS \"$limit\", 'it''s' FROM t LIMIT ?");
+        assert_eq!(order, [1]);
+    }
+
+    #[test]
+    fn apostrophes_in_comments_do_not_hide_parameters() {
+        let (sql, order) = positional("SELECT *\n-- owner's name\nFROM t\nWHERE o = $owner\n/* the owner's $limit */\nLIMIT $limit");
+        assert_eq!(sql, "SELECT *\n-- owner's name\nFROM t\nWHERE o = ?\n/* the owner's $limit */\nLIMIT ?");
+        assert_eq!(order, [0, 1]);
+    }
+
+    #[test]
+    fn comment_at_the_end_of_the_sql() {
+        let (sql, order) = positional("SELECT $owner -- it's the owner");
+        assert_eq!(sql, "SELECT ? -- it's the owner");
+        assert_eq!(order, [0]);
+    }
+
+    #[test]
+    fn rejects_undeclared_parameters() {
+        let report = SavedReport::parse("test", &format!("{}SELECT $missing", HEADER)).unwrap();
+        assert!(matches!(report.positional_sql(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn built_in_reports_parse() {
+        let registry = ReportRegistry::load(None).unwrap();
+        for (name, _) in BUILTIN_REPORTS {
+            registry.get(name).unwrap().positional_sql().unwrap();
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use duckdb::types::Value;
+use duckdb::{Connection, Params, Row, RowIndex};
+use rust_decimal::Decimal;
+
+/// Decodes a value from a result row.
+///
+/// Implementations read columns by name, so a query may select them in any order and
+/// include columns the type doesn't need.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row<'_>) -> duckdb::Result<Self>;
+}
+
+/// Runs `sql` and decodes every result row into a `T`.
+pub fn query_as<T: FromRow>(conn: &Connection, sql: &str, params: impl Params) -> duckdb::Result<Vec<T>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, |row| T::from_row(row))?;
+    rows.collect()
+}
+
+/// Reads a DECIMAL column, which `Row::get` has no `Decimal` conversion for. Integer
+/// columns are read exactly; anything else is a conversion error.
+pub fn get_decimal<I: RowIndex>(row: &Row<'_>, index: I) -> duckdb::Result<Option<Decimal>> {
+    let column = index.idx(row.as_ref())?;
+    match row.get(column)? {
+        Value::Null => Ok(None),
+        Value::Decimal(value) => Ok(Some(value)),
+        Value::TinyInt(value) => Ok(Some(value.into())),
+        Value::SmallInt(value) => Ok(Some(value.into())),
+        Value::Int(value) => Ok(Some(value.into())),
+        Value::BigInt(value) => Ok(Some(value.into())),
+        other => Err(duckdb::Error::InvalidColumnType(
+            column,
+            row.as_ref().column_name(column)?.to_string(),
+            other.data_type(),
+        )),
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs;
+use std::path::Path;
+use duckdb::types::Value;
+use duckdb::params_from_iter;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{nullable, nullable_decimal, ResultSet};
+use crate::row::get_decimal;
+use crate::error::Error;
+
+/// Rules used when no configuration file is given.
+const DEFAULT_RULES: &str = include_str!("../config/distress_rules.toml");
+
+/// A set of weighted distress rules, usually loaded from a TOML file.
+#[derive(Debug, Deserialize)]
+pub struct ScoringConfig {
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+/// A single distress rule: a named signal and the weight it adds when it fires.
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub weight: f64,
+    #[serde(flatten)]
+    pub signal: Signal,
+}
+
+/// The distress signals a rule can test for.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "signal", rename_all = "snake_case")]
+pub enum Signal {
+    /// Owner name is blank or "Unknown"
+    BlankOwner,
+    /// No owner mailing address on record
+    BlankOwnerAddress,
+    /// Tax bills are mailed in care of someone else
+    CareOfPresent,
+    /// Mailing address is outside `home_state`
+    OutOfStateOwner { home_state: String },
+    /// No value appraised for buildings
+    ZeroBuildingValue,
+    /// Structure built before `year`; a `year_built` of zero means unknown and never fires
+    BuiltBefore { year: f64 },
+    /// Mapped to a Special Flood Hazard Area zone, such as A or AE, in `parcel_flood_zone`
+    FloodRisk,
+    /// No recorded sale price
+    NoSaleHistory,
+    /// Deed recorded in book `max_deed_book` or earlier. Deed books are numbered
+    /// sequentially, so a low book number means an old conveyance.
+    OldDeed { max_deed_book: i64 },
+    /// Total appraised value below `below`
+    LowAppraisal { below: f64 },
+}
+
+impl Signal {
+    /// Returns a SQL predicate over a row of `parcel_summary`, which must be selected
+    /// without an alias, pushing its bind values onto `params` in the order their
+    /// placeholders appear.
+    fn predicate(&self, params: &mut Vec<Value>) -> String {
+        let predicate = match self {
+            Signal::BlankOwner => "UPPER(COALESCE(TRIM(full_owner_name), '')) IN ('', 'UNKNOWN')",
+            Signal::BlankOwnerAddress => "COALESCE(TRIM(owner_address), '') = ''",
+            Signal::CareOfPresent => "COALESCE(TRIM(care_of), '') <> ''",
+            Signal::OutOfStateOwner { home_state } => {
+                params.push(Value::Text(home_state.to_uppercase()));
+                "UPPER(COALESCE(TRIM(owner_state), '')) NOT IN ('', ?)"
+            }
+            Signal::ZeroBuildingValue => "COALESCE(building_appraised, 0) = 0",
+            Signal::BuiltBefore { year } => {
+                params.push(Value::Double(*year));
+                "year_built > 0 AND year_built < ?"
+            }
+            Signal::FloodRisk => {
+                "EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        parcel_flood_zone z
+                    WHERE
+                        z.sfha
+                        AND z.parcel_id = parcel_summary.parcel_id
+                        AND z.tax_year IS NOT DISTINCT FROM parcel_summary.tax_year
+                )"
+            }
+            Signal::NoSaleHistory => "COALESCE(sale_price, 0) = 0",
+            Signal::OldDeed { max_deed_book } => {
+                params.push(Value::BigInt(*max_deed_book));
+                "TRY_CAST(TRIM(deed_book) AS BIGINT) <= ?"
+            }
+            Signal::LowAppraisal { below } => {
+                params.push(Value::Double(*below));
+                "total_appraised < ?"
+            }
+        };
+        format!("COALESCE(({}), FALSE)", predicate)
+    }
+}
+
+impl ScoringConfig {
+    /// Loads rules from `path`, or the built-in rules when no path is given.
+    pub fn load(path: Option<&Path>) -> Result<ScoringConfig, Error> {
+        let source = match path {
+            Some(path) => fs::read_to_string(path).map_err(|e| Error::file(path, e))?,
+            None => DEFAULT_RULES.to_string(),
+        };
+        let config: ScoringConfig = toml::from_str(&source).map_err(|source| Error::Toml {
+            path: path.map(Path::to_path_buf),
+            source: Box::new(source),
+        })?;
+        if config.rules.is_empty() {
+            return Err(Error::Config("scoring config defines no rules".to_string()));
+        }
+        Ok(config)
+    }
+
+    /// Returns a SQL expression for the distress score of a `parcel_summary` row, the sum
+    /// of the weights of the rules that fire, pushing its bind values onto `params`.
+    pub fn score_expression(&self, params: &mut Vec<Value>) -> String {
+        self.weighted_sum(params, |_, rule, params| rule.signal.predicate(params))
+    }
+
+    // Sums the weights of the rules whose condition, built by `fired` for each rule in
+    // turn, holds
+    fn weighted_sum(
+        &self,
+        params: &mut Vec<Value>,
+        mut fired: impl FnMut(usize, &Rule, &mut Vec<Value>) -> String,
+    ) -> String {
+        let terms: Vec<String> = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let condition = fired(i, rule, params);
+                params.push(Value::Double(rule.weight));
+                format!("CASE WHEN {} THEN ? ELSE 0 END", condition)
+            })
+            .collect();
+        terms.join(" + ")
+    }
+}
+
+/// A parcel with its composite distress score and the rules that fired.
+struct ScoredParcel {
+    parcel_id: Option<String>,
+    full_owner_name: Option<String>,
+    owner_state: Option<String>,
+    total_appraised: Option<Decimal>,
+    score: f64,
+    reasons: Vec<String>,
+}
+
+/// Scores every parcel against the configured rules and returns those scoring at least
+/// `min_score`, highest first, along with the rules that fired for each.
+pub fn score_distressed_properties(
+    db: &Database,
+    config: &ScoringConfig,
+    min_score: f64,
+    limit: usize,
+    filter: &ParcelFilter,
+) -> Result<ResultSet, Error> {
+
+    // Build one boolean column per rule, then sum the weights of those that fired
+    let mut params: Vec<Value> = Vec::new();
+    let flags: Vec<String> = config
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| format!("{} AS rule_{}", rule.signal.predicate(&mut params), i))
+        .collect();
+    let score = config.weighted_sum(&mut params, |i, _, _| format!("rule_{}", i));
+    let rule_columns: Vec<String> = (0..config.rules.len()).map(|i| format!("rule_{}", i)).collect();
+    params.push(Value::Double(min_score));
+    params.push(Value::BigInt(limit as i64));
+
+    let query = format!(
+        "WITH flags AS (
+            SELECT
+                parcel_id,
+                full_owner_name,
+                owner_state,
+                total_appraised,
+                {}
+            FROM
+                parcel_summary
+        ),
+        scored AS (
+            SELECT
+                *,
+                {} AS score
+            FROM
+                flags
+        )
+        SELECT
+            parcel_id,
+            full_owner_name,
+            owner_state,
+            total_appraised,
+            score,
+            {}
+        FROM
+            scored
+        WHERE
+            score >= ?
+        ORDER BY
+            score DESC,
+            total_appraised ASC
+        LIMIT
+            ?",
+        flags.join(",\n                "),
+        score,
+        rule_columns.join(", "),
+    );
+
+    // Restrict the query to the filtered parcels; the filter's values bind first
+    let (query, values) = filter.scope(&query);
+    params.splice(0..0, values);
+
+    // Prepare and execute the query
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(params_from_iter(params))?;
+
+    // Collect records into a vector, explaining each score by the rules that fired
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut reasons = Vec::new();
+        for (i, rule) in config.rules.iter().enumerate() {
+            let fired: bool = row.get(format!("rule_{}", i).as_str())?;
+            if fired {
+                reasons.push(rule.name.clone());
+            }
+        }
+        records.push(ScoredParcel {
+            parcel_id: row.get("parcel_id")?,
+            full_owner_name: row.get("full_owner_name")?,
+            owner_state: row.get("owner_state")?,
+            total_appraised: get_decimal(row, "total_appraised")?,
+            score: row.get("score")?,
+            reasons,
+        });
+    }
+
+    // Define headers for printing
+    let headers = vec![
+        "Parcel ID",
+        "Owner",
+        "Owner State",
+        "Total Appraised",
+        "Score",
+        "Signals",
+    ];
+
+    // Callback to generate row data
+    let get_row_data = |record: &ScoredParcel| -> Vec<Value> {
+        vec![
+            nullable(record.parcel_id.clone()),
+            nullable(record.full_owner_name.clone()),
+            nullable(record.owner_state.clone()),
+            nullable_decimal(record.total_appraised),
+            record.score.into(),
+            record.reasons.join(", ").into(),
+        ]
+    };
+
+    Ok(ResultSet::new(headers, &records, get_row_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::rebuild_derived_tables;
+
+    const RULES: &str = r#"
+        [[rule]]
+        name = "Blank owner"
+        signal = "blank_owner"
+        weight = 3.0
+
+        [[rule]]
+        name = "Flood"
+        signal = "flood_risk"
+        weight = 0.5
+
+        [[rule]]
+        name = "Cheap"
+        signal = "low_appraisal"
+        below = 1000
+        weight = 2.0
+    "#;
+
+    fn fixture() -> (Database, ScoringConfig) {
+        let db = Database::in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_owner_name, flood_risks, total_appraised)
+            VALUES
+                (1, 'A', 2024, 1, '', 'ZONE AE', 500),
+                (2, 'B', 2024, 1, 'SMITH', 'ZONE X', 500),
+                (3, 'C', 2024, 1, 'UNKNOWN', 'NOT MAPPED', 5000),
+                (4, 'D', 2024, 1, 'JONES', NULL, 5000);",
+        )
+        .unwrap();
+        rebuild_derived_tables(&db).unwrap();
+        (db, toml::from_str(RULES).unwrap())
+    }
+
+    #[test]
+    fn scores_and_explains_the_rules_that_fire() {
+        let (db, config) = fixture();
+        let result = score_distressed_properties(&db, &config, 0.1, 10, &ParcelFilter::default()).unwrap();
+        let scored: Vec<(Value, Value, Value)> = result
+            .rows
+            .iter()
+            .map(|row| (row[0].clone(), row[4].clone(), row[5].clone()))
+            .collect();
+        let expected = [("A", 5.5, "Blank owner, Flood, Cheap"), ("C", 3.0, "Blank owner"), ("B", 2.0, "Cheap")]
+            .map(|(id, score, reasons)| (Value::Text(id.to_string()), Value::Double(score), Value::Text(reasons.to_string())));
+        assert_eq!(scored, expected);
+    }
+
+    #[test]
+    fn score_expression_matches_the_report() {
+        let (db, config) = fixture();
+        let mut params = Vec::new();
+        let sql = format!(
+            "SELECT {} FROM parcel_summary ORDER BY parcel_id",
+            config.score_expression(&mut params)
+        );
+        let mut stmt = db.prepare(&sql).unwrap();
+        let scores: Vec<f64> = stmt
+            .query_map(params_from_iter(params), |row| row.get(0))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(scores, [5.5, 2.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn flood_risk_needs_a_hazard_zone() {
+        let (db, _) = fixture();
+        let mut params = Vec::new();
+        let sql = format!(
+            "SELECT parcel_id FROM parcel_summary WHERE {} ORDER BY parcel_id",
+            Signal::FloodRisk.predicate(&mut params)
+        );
+        let flooded: Vec<String> = db
+            .prepare(&sql)
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<duckdb::Result<_>>()
+            .unwrap();
+        assert_eq!(flooded, ["A"]);
+    }
+}
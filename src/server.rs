@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use duckdb::Connection;
+use serde_json::{json, Value as JsonValue};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::database::Database;
+use crate::filter::ParcelFilter;
+use crate::output::{to_json_rows, ResultSet};
+use crate::geometry::has_parcel_geometry;
+use crate::query;
+use crate::report::ReportRegistry;
+use crate::scoring::ScoringConfig;
+use crate::tiles::TileBuilder;
+use crate::error::Error;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// The map viewer served at `/`, self-contained so it works without internet access.
+const VIEWER: &str = include_str!("viewer.html");
+
+/// A fixed set of connections shared by the request threads.
+struct ConnectionPool {
+    idle: Mutex<Vec<Database>>,
+    returned: Condvar,
+}
+
+impl ConnectionPool {
+    // Clones the database's connection, so every connection shares one database instance
+    fn open(db: &Database, size: usize) -> Result<Self, Error> {
+        let idle = (0..size).map(|_| db.try_clone()).collect::<Result<Vec<_>, Error>>()?;
+        Ok(ConnectionPool {
+            idle: Mutex::new(idle),
+            returned: Condvar::new(),
+        })
+    }
+
+    // Waits for an idle connection; it goes back to the pool when the guard is dropped
+    fn get(&self) -> PooledConnection<'_> {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return PooledConnection { pool: self, conn: Some(conn) };
+            }
+            idle = self.returned.wait(idle).unwrap();
+        }
+    }
+}
+
+struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    conn: Option<Database>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.idle.lock().unwrap().push(conn);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+/// An error response: the HTTP status and a message for the JSON body.
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(error: impl ToString) -> Self {
+        ApiError { status: 400, message: error.to_string() }
+    }
+
+    fn not_found(message: impl ToString) -> Self {
+        ApiError { status: 404, message: message.to_string() }
+    }
+
+    fn internal(error: impl ToString) -> Self {
+        ApiError { status: 500, message: error.to_string() }
+    }
+}
+
+/// A response body: JSON for the API, an encoded vector tile or the viewer page.
+enum Body {
+    Json(JsonValue),
+    Tile(Vec<u8>),
+    Html(&'static str),
+}
+
+/// A page of results: 1-based page number, rows per page and the rows before it.
+struct Page {
+    number: usize,
+    size: usize,
+    offset: usize,
+}
+
+/// The parsed query string of a request, consumed key by key.
+struct Query {
+    pairs: HashMap<String, String>,
+}
+
+impl Query {
+    fn parse(query: &str) -> Self {
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key, true), percent_decode(value, true))
+            })
+            .collect();
+        Query { pairs }
+    }
+
+    fn take(&mut self, key: &str) -> Option<String> {
+        self.pairs.remove(key)
+    }
+
+    fn take_page(&mut self) -> Result<Page, ApiError> {
+        let number: usize = match self.take("page") {
+            Some(page) => page.parse().map_err(|_| ApiError::bad_request("page must be a positive integer"))?,
+            None => 1,
+        };
+        let size: usize = match self.take("per_page") {
+            Some(size) => size.parse().map_err(|_| ApiError::bad_request("per_page must be a positive integer"))?,
+            None => DEFAULT_PAGE_SIZE,
+        };
+        if number == 0 || size == 0 || size > MAX_PAGE_SIZE {
+            return Err(ApiError::bad_request(format!(
+                "page must be at least 1 and per_page between 1 and {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+        // The offset and the row past the page are bound as BIGINT, so both must fit one
+        let offset = (number - 1)
+            .checked_mul(size)
+            .filter(|offset| offset.checked_add(size + 1).is_some_and(|end| i64::try_from(end).is_ok()))
+            .ok_or_else(|| ApiError::bad_request("page is too large"))?;
+        Ok(Page { number, size, offset })
+    }
+
+    // Moves the filter parameters into a `ParcelFilter`, leaving the rest
+    fn take_filter(&mut self) -> Result<ParcelFilter, ApiError> {
+        let mut filter = ParcelFilter::default();
+        let mut rest = HashMap::new();
+        for (key, value) in self.pairs.drain() {
+            let is_filter = filter
+                .set(&key, &value)
+                .map_err(|e| ApiError::bad_request(format!("invalid value '{}' for {}: {}", value, key, e)))?;
+            if !is_filter {
+                rest.insert(key, value);
+            }
+        }
+        self.pairs = rest;
+        Ok(filter)
+    }
+
+    // Fails on parameters nothing has taken, so typos don't silently return everything
+    fn finish(self) -> Result<(), ApiError> {
+        match self.pairs.keys().next() {
+            Some(key) => Err(ApiError::bad_request(format!("unknown parameter '{}'", key))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Serves the parcel database as a JSON API, vector tiles and a map viewer on `addr` until
+/// the process is stopped. Tile features carry `parcel_id` and the `tile_attributes`
+/// columns, with distress scores from `scoring`.
+///
+/// Each of the `threads` request threads borrows a connection to `db` from a pool of the
+/// same size. The server never writes, so `db` can be opened read-only and shared with
+/// other processes.
+pub fn serve(
+    db: &Database,
+    addr: &str,
+    threads: usize,
+    registry: &ReportRegistry,
+    tile_attributes: &[String],
+    scoring: &ScoringConfig,
+) -> Result<(), Error> {
+    let threads = threads.max(1);
+    let pool = ConnectionPool::open(db, threads)?;
+    let tiles = TileBuilder::new(&pool.get(), tile_attributes, scoring)?;
+    let server = Server::http(addr).map_err(|e| io::Error::other(format!("cannot listen on {}: {}", addr, e)))?;
+    println!("Serving {} on http://{}", db.name(), server.server_addr());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                match server.recv() {
+                    Ok(request) => handle(request, &pool, registry, &tiles),
+                    Err(e) => eprintln!("Error receiving request: {}", e),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(request: Request, pool: &ConnectionPool, registry: &ReportRegistry, tiles: &TileBuilder) {
+    let result = if *request.method() == Method::Get {
+        route(request.url(), pool, registry, tiles)
+    } else {
+        Err(ApiError { status: 405, message: "only GET is supported".to_string() })
+    };
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(error) => (error.status, Body::Json(json!({ "error": error.message }))),
+    };
+
+    let (content_type, data) = match body {
+        Body::Json(value) => ("application/json", value.to_string().into_bytes()),
+        Body::Tile(tile) => ("application/vnd.mapbox-vector-tile", tile),
+        Body::Html(page) => ("text/html; charset=utf-8", page.as_bytes().to_vec()),
+    };
+    let response = Response::from_data(data)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).expect("header is valid"));
+    if let Err(e) = request.respond(response) {
+        eprintln!("Error sending response: {}", e);
+    }
+}
+
+fn route(url: &str, pool: &ConnectionPool, registry: &ReportRegistry, tiles: &TileBuilder) -> Result<Body, ApiError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut query = Query::parse(query);
+    let segments: Vec<String> = path
+        .trim_matches('/')
+        .split('/')
+        .map(|segment| percent_decode(segment, false))
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let body = match segments.as_slice() {
+        [""] => {
+            query.finish()?;
+            return Ok(Body::Html(VIEWER));
+        }
+        ["api", "parcels", parcel_id] => {
+            let conn = pool.get();
+            let record = query::get_parcel(&conn, parcel_id).map_err(ApiError::internal)?;
+            match record {
+                Some(record) => serde_json::to_value(record).map_err(ApiError::internal)?,
+                None => return Err(ApiError::not_found(format!("no parcel with ID '{}'", parcel_id))),
+            }
+        }
+        ["api", "owners"] => {
+            let name = query.take("q").ok_or_else(|| ApiError::bad_request("owners needs ?q=NAME"))?;
+            let page = query.take_page()?;
+            let filter = query.take_filter()?;
+            query.finish()?;
+
+            // Fetch one extra row to tell whether there is a next page
+            let conn = pool.get();
+            // The name is matched literally, so `%` and `_` in it aren't wildcards
+            let escaped = name.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            let pattern = format!("%{}%", escaped);
+            let result = query::search_owners(&conn, &pattern, &filter, page.offset, page.size + 1)
+                .map_err(ApiError::internal)?;
+            paged(result, &page)
+        }
+        ["api", "land-uses"] => {
+            let filter = query.take_filter()?;
+            query.finish()?;
+            let conn = pool.get();
+            let result = query::get_land_uses(&conn, &filter).map_err(ApiError::internal)?;
+            JsonValue::Array(to_json_rows(&result))
+        }
+        ["api", "reports"] => {
+            query.finish()?;
+            JsonValue::Array(to_json_rows(&registry.list()))
+        }
+        ["api", "reports", name] => {
+            let report = registry.get(name).map_err(ApiError::not_found)?;
+            let page = query.take_page()?;
+
+            // A key the report declares is its parameter, even when a filter shares the name
+            let mut args = HashMap::new();
+            for param in &report.meta.params {
+                if let Some(value) = query.take(&param.name) {
+                    args.insert(param.name.clone(), value);
+                }
+            }
+            let filter = query.take_filter()?;
+            query.finish()?;
+
+            // A report's own limit would otherwise cut it short of the later pages
+            if report.has_param("limit") && !args.contains_key("limit") {
+                args.insert("limit".to_string(), (page.offset + page.size + 1).to_string());
+            }
+
+            // Fetch one extra row to tell whether there is a next page
+            let conn = pool.get();
+            let result = report
+                .run_page(&conn, &args, &filter, page.offset, page.size + 1)
+                .map_err(|e| match e {
+                    Error::InvalidInput(_) => ApiError::bad_request(e),
+                    _ => ApiError::internal(e),
+                })?;
+            let mut body = paged(result, &page);
+            body["report"] = json!(report.name);
+            body["title"] = json!(report.meta.title);
+            body
+        }
+        ["api", "bounds"] => {
+            query.finish()?;
+            let conn = pool.get();
+            if !has_parcel_geometry(&conn).map_err(ApiError::internal)? {
+                return Err(ApiError::not_found("no parcel geometry is loaded"));
+            }
+            let (min_x, min_y, max_x, max_y): (Option<f64>, Option<f64>, Option<f64>, Option<f64>) = conn
+                .query_row(
+                    "SELECT MIN(min_x), MIN(min_y), MAX(max_x), MAX(max_y) FROM parcel_geometry",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .map_err(ApiError::internal)?;
+            json!({ "min_x": min_x, "min_y": min_y, "max_x": max_x, "max_y": max_y })
+        }
+        ["tiles", z, x, file] if file.ends_with(".mvt") => {
+            query.finish()?;
+            let y = file.trim_end_matches(".mvt");
+            let not_found = || ApiError::not_found(format!("there is no tile {}/{}/{}", z, x, y));
+            let z: u8 = z.parse().map_err(|_| not_found())?;
+            let x: u32 = x.parse().map_err(|_| not_found())?;
+            let y: u32 = y.parse().map_err(|_| not_found())?;
+            if z > 24 || x >= 1 << z || y >= 1 << z {
+                return Err(not_found());
+            }
+
+            let conn = pool.get();
+            if !has_parcel_geometry(&conn).map_err(ApiError::internal)? {
+                return Err(ApiError::not_found("no parcel geometry is loaded"));
+            }
+            return Ok(Body::Tile(tiles.tile(&conn, z, x, y).map_err(ApiError::internal)?));
+        }
+        _ => return Err(ApiError::not_found(format!("no route for {}", path))),
+    };
+    Ok(Body::Json(body))
+}
+
+// Wraps one page of `result` in a paging envelope; one row past the page means there is a
+// next page.
+fn paged(mut result: ResultSet, page: &Page) -> JsonValue {
+    let has_more = result.rows.len() > page.size;
+    result.rows.truncate(page.size);
+    json!({
+        "page": page.number,
+        "per_page": page.size,
+        "has_more": has_more,
+        "columns": result.headers,
+        "rows": to_json_rows(&result),
+    })
+}
+
+// Decodes %XX escapes, and `+` as a space in query strings
+fn percent_decode(text: &str, plus_as_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let escape = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match escape {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ten owners, OWNER 01 to OWNER 10, worth 1,000 to 10,000
+    fn fixture() -> (ConnectionPool, ReportRegistry, TileBuilder) {
+        let db = Database::in_memory().unwrap();
+        let values: Vec<String> = (1..=10)
+            .map(|i| format!("({}, '02-01-{:04}', 2024, 1, 'OWNER {:02}', {})", i, i, i, i * 1000))
+            .collect();
+        db.execute_batch(&format!(
+            "INSERT INTO parcel (id, parcel_id, tax_year, card, full_owner_name, total_appraised) VALUES {};",
+            values.join(", ")
+        ))
+        .unwrap();
+        let pool = ConnectionPool::open(&db, 1).unwrap();
+        let tiles = TileBuilder::new(&pool.get(), &[], &ScoringConfig::load(None).unwrap()).unwrap();
+        (pool, ReportRegistry::load(None).unwrap(), tiles)
+    }
+
+    fn get(url: &str) -> Result<JsonValue, ApiError> {
+        let (pool, registry, tiles) = fixture();
+        match route(url, &pool, &registry, &tiles)? {
+            Body::Json(value) => Ok(value),
+            _ => panic!("{} is not JSON", url),
+        }
+    }
+
+    #[test]
+    fn pages_reports_past_their_default_limit() {
+        let page = get("/api/reports/owners-by-value?page=3&per_page=3").unwrap();
+        let owners: Vec<&str> = page["rows"].as_array().unwrap().iter().map(|row| row["Owner"].as_str().unwrap()).collect();
+        assert_eq!(owners, ["OWNER 04", "OWNER 03", "OWNER 02"]);
+        assert_eq!(page["has_more"], json!(true));
+
+        let last = get("/api/reports/owners-by-value?page=4&per_page=3").unwrap();
+        assert_eq!(last["rows"].as_array().unwrap().len(), 1);
+        assert_eq!(last["has_more"], json!(false));
+
+        // A limit the caller asks for still applies
+        let limited = get("/api/reports/owners-by-value?page=2&per_page=3&limit=4").unwrap();
+        assert_eq!(limited["rows"].as_array().unwrap().len(), 1);
+        assert_eq!(limited["has_more"], json!(false));
+    }
+
+    #[test]
+    fn searches_owner_names_literally() {
+        let owners = |url: &str| get(url).unwrap()["rows"].as_array().unwrap().len();
+        assert_eq!(owners("/api/owners?q=owner%2001"), 1);
+        assert_eq!(owners("/api/owners?q=OWNER_0"), 0);
+        assert_eq!(owners("/api/owners?q=%25"), 0);
+        assert_eq!(owners("/api/owners?q=%5C"), 0);
+    }
+
+    #[test]
+    fn rejects_pages_past_the_end_of_usize() {
+        let error = get(&format!("/api/reports/owners-by-value?page={}&per_page=1000", usize::MAX)).err().unwrap();
+        assert_eq!(error.status, 400);
+        let error = get(&format!("/api/owners?q=OWNER&page={}", usize::MAX / 50)).err().unwrap();
+        assert_eq!(error.status, 400);
+    }
+
+    #[test]
+    fn report_parameter_errors_are_bad_requests() {
+        let error = get("/api/reports/owners-by-value?limit=many").err().unwrap();
+        assert_eq!(error.status, 400);
+    }
+}
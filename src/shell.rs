@@ -0,0 +1,283 @@
+use clap::ValueEnum;
+use duckdb::Connection;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use crate::database::Database;
+use crate::output::{Output, OutputFormat, ResultSet};
+use crate::pretty_print::TableStyle;
+use crate::filter::ParcelFilter;
+use crate::report::{parse_args, ReportRegistry};
+use crate::error::Error;
+
+const HISTORY_FILE: &str = ".parcel_shell_history";
+
+const HELP: &str = "\
+Enter SQL terminated by ';'. Statements may span several lines.
+
+.tables                 List tables and views
+.schema [TABLE]         Show the columns of a table, or of every table
+.reports                List the saved reports and their parameters
+.report NAME [K=V ...]  Run a saved report; a bare number is taken as its limit
+.describe NAME          Show a saved report's parameters
+.format FORMAT          Set the output format: table, csv, json or ndjson
+.style STYLE            Set the table style: plain, markdown, boxed or compact
+.help                   Show this message
+.quit                   Leave the shell";
+
+/// Completes table and column names, most usefully the 90-odd columns of `parcel`.
+struct ShellHelper {
+    words: Vec<String>,
+}
+
+impl ShellHelper {
+    fn load(conn: &Connection) -> duckdb::Result<Self> {
+        let mut stmt = conn.prepare(
+            "SELECT table_name FROM duckdb_tables()
+            UNION
+            SELECT view_name FROM duckdb_views() WHERE NOT internal
+            UNION
+            SELECT column_name FROM duckdb_columns() WHERE table_name = 'parcel'
+            ORDER BY 1",
+        )?;
+        let words = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<duckdb::Result<Vec<String>>>()?;
+        Ok(ShellHelper { words })
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix = line[start..pos].to_lowercase();
+        if prefix.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+        let candidates = self
+            .words
+            .iter()
+            .filter(|word| word.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Runs an interactive SQL shell against `db`.
+///
+/// Results are written through `output`, whose format and table style can be changed
+/// from inside the shell. Saved reports run with `.report` see only the parcels matching
+/// `filter`; plain SQL is not filtered. History is kept in `.parcel_shell_history` in the
+/// working directory.
+pub fn run_shell(
+    db: &Database,
+    registry: &ReportRegistry,
+    filter: &ParcelFilter,
+    mut output: Output,
+) -> Result<(), Error> {
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper::load(db)?));
+    // A missing history file just means this is the first session
+    let _ = editor.load_history(HISTORY_FILE);
+
+    println!("Connected to {}. Type .help for commands.", db.name());
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() { "parcel> " } else { "   ...> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Error::Readline(e)),
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(trimmed)?;
+
+        // Meta-commands are only recognised at the start of a statement
+        if statement.is_empty() && trimmed.starts_with('.') {
+            match run_meta_command(db, registry, filter, trimmed, &mut output) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        statement.push_str(&line);
+        statement.push('\n');
+        if !ends_statement(&statement) {
+            continue;
+        }
+        let result = ResultSet::query(db, &statement, []);
+        statement.clear();
+        match result {
+            Ok(result) => {
+                if let Err(e) = output.write(&result) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    editor.save_history(HISTORY_FILE)?;
+    Ok(())
+}
+
+// Whether `sql` ends in a `;` once trailing comments and whitespace are dropped. A `;`
+// inside a string, quoted identifier or comment doesn't count, nor does one followed by a
+// string or block comment that is still open.
+fn ends_statement(sql: &str) -> bool {
+    let mut chars = sql.chars().peekable();
+    let mut last = None;
+    while let Some(c) = chars.next() {
+        match c {
+            // A doubled quote inside a string reads as two strings back to back
+            '\'' | '"' => {
+                if !chars.by_ref().any(|next| next == c) {
+                    return false;
+                }
+                last = Some(c);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&next| next == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == Some('*') => break,
+                        Some(next) => previous = Some(next),
+                        None => return false,
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => last = Some(c),
+        }
+    }
+    last == Some(';')
+}
+
+// Returns false when the shell should exit
+fn run_meta_command(
+    conn: &Connection,
+    registry: &ReportRegistry,
+    filter: &ParcelFilter,
+    line: &str,
+    output: &mut Output,
+) -> Result<bool, Error> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let argument = words.next();
+    let usage = |text: &str| Error::InvalidInput(format!("usage: {}", text));
+
+    match command {
+        ".quit" | ".exit" => return Ok(false),
+        ".help" => println!("{}", HELP),
+        ".tables" => output.write(&ResultSet::query(
+            conn,
+            "SELECT table_name AS name, 'table' AS kind FROM duckdb_tables()
+            UNION ALL
+            SELECT view_name, 'view' FROM duckdb_views() WHERE NOT internal
+            ORDER BY name",
+            [],
+        )?)?,
+        ".schema" => output.write(&ResultSet::query(
+            conn,
+            "SELECT table_name, column_name, data_type, is_nullable
+            FROM duckdb_columns()
+            WHERE NOT internal AND (?::TEXT IS NULL OR table_name = ?)
+            ORDER BY table_name, column_index",
+            [argument, argument],
+        )?)?,
+        ".reports" => output.write(&registry.list())?,
+        ".report" => {
+            let report = registry.get(argument.ok_or_else(|| usage(".report NAME [KEY=VALUE ...]"))?)?;
+            let args: Vec<String> = words
+                .map(|word| match word.parse::<usize>() {
+                    Ok(limit) => format!("limit={}", limit),
+                    Err(_) => word.to_string(),
+                })
+                .collect();
+            output.write(&report.run(conn, &parse_args(&args)?, filter)?)?;
+        }
+        ".describe" => {
+            output.write(&registry.get(argument.ok_or_else(|| usage(".describe NAME"))?)?.describe())?
+        }
+        ".format" => {
+            let format = OutputFormat::from_str(argument.ok_or_else(|| usage(".format FORMAT"))?, true)
+                .map_err(Error::InvalidInput)?;
+            if format == OutputFormat::Parquet {
+                return Err(Error::InvalidInput(
+                    "parquet needs a file; run the report from the command line with --output".to_string(),
+                ));
+            }
+            output.format = format;
+        }
+        ".style" => {
+            output.table.style = TableStyle::from_str(argument.ok_or_else(|| usage(".style STYLE"))?, true)
+                .map_err(Error::InvalidInput)?;
+        }
+        _ => return Err(Error::InvalidInput(format!("unknown command {}; try .help", command))),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_a_statement_at_a_semicolon_before_comments() {
+        assert!(ends_statement("SELECT 1;\n"));
+        assert!(ends_statement("SELECT 1; -- note\n"));
+        assert!(ends_statement("SELECT 1;  /* note */  \n"));
+        assert!(ends_statement("SELECT\n  1 -- first\n;\n"));
+        assert!(ends_statement("SELECT 'it''s';\n"));
+    }
+
+    #[test]
+    fn ignores_semicolons_in_strings_and_comments() {
+        assert!(!ends_statement("SELECT 1\n"));
+        assert!(!ends_statement("SELECT 1 -- stop here;\n"));
+        assert!(!ends_statement("SELECT 1 /* stop here; */\n"));
+        assert!(!ends_statement("SELECT 'a;\n"));
+        assert!(!ends_statement("SELECT \"odd;name\n"));
+        assert!(!ends_statement("SELECT 1; /* still open\n"));
+        assert!(!ends_statement("SELECT 1; 'x\n"));
+    }
+}
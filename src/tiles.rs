@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Connection};
+use rust_decimal::prelude::ToPrimitive;
+use crate::geometry::{from_wkb, has_parcel_geometry, signed_area, MultiPolygon, WORLD_EXTENT};
+use crate::scoring::ScoringConfig;
+use crate::error::Error;
+
+/// Tile coordinates run from 0 to this across a tile.
+const EXTENT: f64 = 4096.0;
+
+/// Geometry is kept this far outside the tile so outlines don't show seams at tile edges.
+const BUFFER: f64 = 64.0;
+
+/// Name of the layer parcels are drawn in.
+pub const LAYER: &str = "parcels";
+
+/// Attribute holding the parcel's distress score, computed from the scoring rules.
+pub const DISTRESS_SCORE: &str = "distress_score";
+
+/// The attributes carried on tile features when none are configured.
+pub const DEFAULT_ATTRIBUTES: &[&str] = &["full_owner_name", "total_appraised", "land_use", DISTRESS_SCORE];
+
+/// Builds Mapbox Vector Tiles of parcel outlines from the `parcel_geometry` table.
+pub struct TileBuilder {
+    sql: String,
+    params: Vec<Value>,
+    attributes: Vec<String>,
+}
+
+impl TileBuilder {
+    /// Prepares tiles carrying `parcel_id` and the given attributes. Attributes are
+    /// `parcel_summary` columns, checked against the view so only real column names reach
+    /// the SQL, or `distress_score` as scored by `scoring`.
+    pub fn new(conn: &Connection, attributes: &[String], scoring: &ScoringConfig) -> Result<Self, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT column_name FROM duckdb_columns() WHERE table_name = 'parcel_summary'",
+        )?;
+        let columns = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<duckdb::Result<Vec<String>>>()?;
+        let is_known = |name: &String| name == DISTRESS_SCORE || columns.contains(name);
+        if let Some(unknown) = attributes.iter().find(|name| !is_known(name)) {
+            return Err(Error::InvalidInput(format!(
+                "unknown tile attribute {}; use a parcel_summary column or {}",
+                unknown, DISTRESS_SCORE
+            )));
+        }
+
+        // The score is only worked out when a feature carries it
+        let mut params = Vec::new();
+        let score = if attributes.iter().any(|name| name == DISTRESS_SCORE) {
+            format!(",\n                    {} AS {}", scoring.score_expression(&mut params), DISTRESS_SCORE)
+        } else {
+            String::new()
+        };
+
+        let selected: Vec<String> = attributes
+            .iter()
+            .map(|name| format!(", p.\"{}\"", name))
+            .collect();
+        let sql = format!(
+            "WITH latest AS (
+                SELECT
+                    *{}
+                FROM
+                    parcel_summary
+                QUALIFY
+                    ROW_NUMBER() OVER (PARTITION BY parcel_id ORDER BY tax_year DESC NULLS LAST) = 1
+            )
+            SELECT
+                g.wkb,
+                COALESCE(g.parcel_id, g.source_id){}
+            FROM
+                parcel_geometry g
+                LEFT JOIN latest p
+                    ON p.parcel_id = g.parcel_id
+            WHERE
+                g.max_x >= ?
+                AND g.min_x <= ?
+                AND g.max_y >= ?
+                AND g.min_y <= ?",
+            score,
+            selected.concat()
+        );
+        Ok(TileBuilder {
+            sql,
+            params,
+            attributes: attributes.to_vec(),
+        })
+    }
+
+    /// Encodes tile `z`/`x`/`y`; a tile with no parcels is empty. Errors when no geometry
+    /// has been loaded.
+    pub fn tile(&self, conn: &Connection, z: u8, x: u32, y: u32) -> Result<Vec<u8>, Error> {
+        if !has_parcel_geometry(conn)? {
+            return Err(Error::NotFound(
+                "no parcel geometry is loaded; run the `geometry` subcommand first".to_string(),
+            ));
+        }
+        if z > 24 || x >= 1 << z || y >= 1 << z {
+            return Err(Error::NotFound(format!("there is no tile {}/{}/{}", z, x, y)));
+        }
+
+        // Bounds of the tile and its buffer in Web Mercator metres
+        let size = 2.0 * WORLD_EXTENT / f64::from(1u32 << z);
+        let min_x = -WORLD_EXTENT + f64::from(x) * size;
+        let max_y = WORLD_EXTENT - f64::from(y) * size;
+        let margin = size * BUFFER / EXTENT;
+
+        let mut values = self.params.clone();
+        values.extend(
+            [min_x - margin, min_x + size + margin, max_y - size - margin, max_y + margin].map(Value::Double),
+        );
+        let mut stmt = conn.prepare(&self.sql)?;
+        let mut rows = stmt.query(params_from_iter(values))?;
+
+        let mut layer = Layer::new(LAYER);
+        while let Some(row) = rows.next()? {
+            let wkb: Vec<u8> = row.get(0)?;
+            let to_tile = |(px, py): (f64, f64)| ((px - min_x) / size * EXTENT, (max_y - py) / size * EXTENT);
+            let geometry = encode_polygons(&from_wkb(&wkb)?, to_tile);
+            if geometry.is_empty() {
+                continue;
+            }
+
+            let mut properties = vec![("parcel_id".to_string(), row.get::<_, Value>(1)?)];
+            for (i, name) in self.attributes.iter().enumerate() {
+                properties.push((name.clone(), row.get::<_, Value>(2 + i)?));
+            }
+            layer.add_feature(&properties, geometry);
+        }
+
+        let mut tile = Vec::new();
+        if !layer.features.is_empty() {
+            write_bytes(&mut tile, 3, &layer.encode());
+        }
+        Ok(tile)
+    }
+}
+
+/// One layer of a tile, with its keys and values interned as the format requires.
+struct Layer {
+    name: String,
+    keys: Vec<String>,
+    values: Vec<Vec<u8>>,
+    /// The index of each of `keys` and `values`, so interning doesn't search them
+    key_indexes: HashMap<String, u32>,
+    value_indexes: HashMap<Vec<u8>, u32>,
+    features: Vec<Vec<u8>>,
+}
+
+impl Layer {
+    fn new(name: &str) -> Self {
+        Layer {
+            name: name.to_string(),
+            keys: Vec::new(),
+            values: Vec::new(),
+            key_indexes: HashMap::new(),
+            value_indexes: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    fn add_feature(&mut self, properties: &[(String, Value)], geometry: Vec<u32>) {
+        let mut tags = Vec::new();
+        for (key, value) in properties {
+            // NULL attributes are left off the feature
+            let Some(value) = encode_value(value) else { continue };
+            tags.push(intern(&mut self.keys, &mut self.key_indexes, key.clone()));
+            tags.push(intern(&mut self.values, &mut self.value_indexes, value));
+        }
+
+        let mut feature = Vec::new();
+        write_varint_field(&mut feature, 1, self.features.len() as u64 + 1);
+        write_packed(&mut feature, 2, &tags);
+        // Geometry type 3 is POLYGON
+        write_varint_field(&mut feature, 3, 3);
+        write_packed(&mut feature, 4, &geometry);
+        self.features.push(feature);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut layer = Vec::new();
+        write_varint_field(&mut layer, 15, 2);
+        write_bytes(&mut layer, 1, self.name.as_bytes());
+        for feature in &self.features {
+            write_bytes(&mut layer, 2, feature);
+        }
+        for key in &self.keys {
+            write_bytes(&mut layer, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            write_bytes(&mut layer, 4, value);
+        }
+        write_varint_field(&mut layer, 5, EXTENT as u64);
+        layer
+    }
+}
+
+// Returns the index of `item` in `items`, adding it if it isn't there yet
+fn intern<T: Clone + Eq + Hash>(items: &mut Vec<T>, indexes: &mut HashMap<T, u32>, item: T) -> u32 {
+    *indexes.entry(item).or_insert_with_key(|item| {
+        items.push(item.clone());
+        items.len() as u32 - 1
+    })
+}
+
+// Encodes an attribute as a tile Value message
+fn encode_value(value: &Value) -> Option<Vec<u8>> {
+    let mut encoded = Vec::new();
+    match value {
+        Value::Null => return None,
+        Value::Boolean(v) => write_varint_field(&mut encoded, 7, u64::from(*v)),
+        Value::TinyInt(_) | Value::SmallInt(_) | Value::Int(_) | Value::BigInt(_) => {
+            let v = match value {
+                Value::TinyInt(v) => i64::from(*v),
+                Value::SmallInt(v) => i64::from(*v),
+                Value::Int(v) => i64::from(*v),
+                Value::BigInt(v) => *v,
+                _ => unreachable!(),
+            };
+            write_varint_field(&mut encoded, 6, zigzag(v));
+        }
+        Value::Float(v) => {
+            write_key(&mut encoded, 3, 1);
+            encoded.extend(f64::from(*v).to_le_bytes());
+        }
+        Value::Double(v) => {
+            write_key(&mut encoded, 3, 1);
+            encoded.extend(v.to_le_bytes());
+        }
+        // Styles compare values numerically, so decimals go as doubles rather than text
+        Value::Decimal(v) => {
+            write_key(&mut encoded, 3, 1);
+            encoded.extend(v.to_f64().unwrap_or_default().to_le_bytes());
+        }
+        Value::Text(v) => write_bytes(&mut encoded, 1, v.as_bytes()),
+        other => write_bytes(&mut encoded, 1, crate::output::format_value(other).as_bytes()),
+    }
+    Some(encoded)
+}
+
+// Turns outlines into tile geometry commands: each ring is clipped to the buffered tile,
+// snapped to whole tile units and wound clockwise on screen if outer, anticlockwise if a hole
+fn encode_polygons(outline: &MultiPolygon, to_tile: impl Fn((f64, f64)) -> (f64, f64)) -> Vec<u32> {
+    let mut commands = Vec::new();
+    let mut cursor = (0i64, 0i64);
+    for polygon in outline {
+        for (i, ring) in polygon.iter().enumerate() {
+            let projected: Vec<(f64, f64)> = ring.iter().map(|point| to_tile(*point)).collect();
+            let mut points: Vec<(i64, i64)> = Vec::new();
+            for (x, y) in clip(&projected, -BUFFER, EXTENT + BUFFER) {
+                let point = (x.round() as i64, y.round() as i64);
+                if points.last() != Some(&point) {
+                    points.push(point);
+                }
+            }
+            while points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            let float_points: Vec<(f64, f64)> = points.iter().map(|(x, y)| (*x as f64, *y as f64)).collect();
+            let area = signed_area(&float_points);
+            if points.len() < 3 || area == 0.0 {
+                // A hole too small to see is dropped; an outer ring takes its holes with it
+                if i == 0 {
+                    break;
+                }
+                continue;
+            }
+
+            // With y pointing down, the format's clockwise outer rings have positive area
+            let is_outer = i == 0;
+            if (area > 0.0) != is_outer {
+                points.reverse();
+            }
+
+            commands.push(command(1, 1));
+            for (j, point) in points.iter().enumerate() {
+                if j == 1 {
+                    commands.push(command(2, points.len() as u32 - 1));
+                }
+                commands.push(zigzag(point.0 - cursor.0) as u32);
+                commands.push(zigzag(point.1 - cursor.1) as u32);
+                cursor = *point;
+            }
+            commands.push(command(7, 1));
+        }
+    }
+    commands
+}
+
+// Sutherland–Hodgman clipping of a ring to the square from `min` to `max` on both axes
+fn clip(ring: &[(f64, f64)], min: f64, max: f64) -> Vec<(f64, f64)> {
+    let edges: [(usize, f64, bool); 4] = [(0, min, true), (0, max, false), (1, min, true), (1, max, false)];
+    let mut points = ring.to_vec();
+    for (axis, bound, keep_above) in edges {
+        if points.is_empty() {
+            break;
+        }
+        let coordinate = |p: &(f64, f64)| if axis == 0 { p.0 } else { p.1 };
+        let inside = |p: &(f64, f64)| if keep_above { coordinate(p) >= bound } else { coordinate(p) <= bound };
+        let crossing = |a: &(f64, f64), b: &(f64, f64)| {
+            let t = (bound - coordinate(a)) / (coordinate(b) - coordinate(a));
+            (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+        };
+
+        let mut clipped = Vec::with_capacity(points.len());
+        for (i, current) in points.iter().enumerate() {
+            let previous = &points[(i + points.len() - 1) % points.len()];
+            match (inside(previous), inside(current)) {
+                (true, true) => clipped.push(*current),
+                (true, false) => clipped.push(crossing(previous, current)),
+                (false, true) => {
+                    clipped.push(crossing(previous, current));
+                    clipped.push(*current);
+                }
+                (false, false) => {}
+            }
+        }
+        points = clipped;
+    }
+    points
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// Protocol buffer encoding, just enough for the vector tile messages
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u32, wire_type: u8) {
+    write_varint(buf, (u64::from(field) << 3) | u64::from(wire_type));
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_key(buf, field, 0);
+    write_varint(buf, value);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buf, field, 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, u64::from(*value));
+    }
+    write_bytes(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zigzag_interleaves_signs() {
+        assert_eq!([0, -1, 1, -2, 2].map(zigzag), [0, 1, 2, 3, 4]);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn varints_use_seven_bits_a_byte() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        write_varint(&mut buf, u64::MAX);
+        assert_eq!(buf[..3], [0x01, 0xac, 0x02]);
+        assert_eq!(buf.len(), 3 + 10);
+    }
+
+    #[test]
+    fn encodes_square_as_one_clockwise_ring() {
+        // Points are already in tile units; with y pointing down this ring runs anticlockwise,
+        // so it is reversed
+        let outline = vec![vec![vec![(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)]]];
+        let commands = encode_polygons(&outline, |point| point);
+        let delta = |d: i64| zigzag(d) as u32;
+        assert_eq!(
+            commands,
+            vec![
+                command(1, 1),
+                delta(10),
+                delta(0),
+                command(2, 3),
+                delta(0),
+                delta(10),
+                delta(-10),
+                delta(0),
+                delta(0),
+                delta(-10),
+                command(7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn clips_rings_to_the_buffered_tile() {
+        let ring = vec![(-1000.0, -1000.0), (-1000.0, 1000.0), (1000.0, 1000.0), (1000.0, -1000.0)];
+        let clipped = clip(&ring, -BUFFER, EXTENT + BUFFER);
+        assert!(clipped.iter().all(|(x, y)| (-BUFFER..=EXTENT + BUFFER).contains(x) && (-BUFFER..=EXTENT + BUFFER).contains(y)));
+
+        let outside = vec![(-500.0, -500.0), (-500.0, -400.0), (-400.0, -400.0)];
+        assert!(encode_polygons(&vec![vec![outside]], |point| point).is_empty());
+    }
+
+    #[test]
+    fn leaves_null_attributes_off_features() {
+        let mut layer = Layer::new(LAYER);
+        layer.add_feature(
+            &[
+                ("parcel_id".to_string(), Value::Text("02-01-0001".to_string())),
+                ("owner".to_string(), Value::Null),
+                ("units".to_string(), Value::Int(2)),
+            ],
+            vec![command(1, 1), 0, 0],
+        );
+        assert_eq!(layer.keys, ["parcel_id", "units"]);
+        assert_eq!(layer.values, [encode_value(&Value::Text("02-01-0001".to_string())).unwrap(), vec![0x30, 4]]);
+    }
+
+    #[test]
+    fn interns_keys_and_values_once_per_layer() {
+        let mut layer = Layer::new(LAYER);
+        for (parcel_id, units) in [("02-01-0001", 2), ("02-01-0002", 2), ("02-01-0001", 3)] {
+            layer.add_feature(
+                &[
+                    ("parcel_id".to_string(), Value::Text(parcel_id.to_string())),
+                    ("units".to_string(), Value::Int(units)),
+                ],
+                vec![command(1, 1), 0, 0],
+            );
+        }
+        assert_eq!(layer.keys, ["parcel_id", "units"]);
+        assert_eq!(layer.values.len(), 4);
+        assert_eq!(layer.value_indexes[&vec![0x30, 6]], 3);
+        assert_eq!(layer.key_indexes.len(), layer.keys.len());
+    }
+}